@@ -1,14 +1,38 @@
 use super::token;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+// Identifies a variable use so the resolver can record its scope distance.
+pub fn next_id() -> usize {
+    NEXT_ID.fetch_add(1, Ordering::Relaxed)
+}
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum Expr {
+    Assign(Box<Assign>),
     Binary(Box<Binary>),
+    Call(Box<Call>),
+    Get(Box<Get>),
     Grouping(Box<Grouping>),
     Literal(Box<Literal>),
+    Logical(Box<Logical>),
+    Set(Box<Set>),
+    Super(Box<Super>),
+    This(Box<This>),
     Unary(Box<Unary>),
+    Variable(Box<Variable>),
     None,
 }
 
+#[derive(Debug, PartialEq, Clone)]
+pub struct Assign {
+    pub id: usize,
+    pub name: token::Token,
+    pub value: Expr,
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub struct Binary {
     pub left: Expr,
@@ -16,14 +40,56 @@ pub struct Binary {
     pub right: Expr,
 }
 
+#[derive(Debug, PartialEq, Clone)]
+pub struct Call {
+    pub callee: Expr,
+    pub paren: token::Token,
+    pub arguments: Vec<Expr>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Get {
+    pub object: Expr,
+    pub name: token::Token,
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub struct Grouping {
     pub exp: Expr,
+    pub span: token::Span,
 }
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct Literal {
     pub value: token::Value,
+    pub span: token::Span,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Logical {
+    pub left: Expr,
+    pub op: token::Token,
+    pub right: Expr,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Set {
+    pub object: Expr,
+    pub name: token::Token,
+    pub value: Expr,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Super {
+    pub id: usize,
+    pub keyword: token::Token,
+    pub method: token::Token,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct This {
+    pub id: usize,
+    pub keyword: token::Token,
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -31,3 +97,125 @@ pub struct Unary {
     pub op: token::Token,
     pub right: Expr,
 }
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Variable {
+    pub id: usize,
+    pub name: token::Token,
+}
+
+impl Expr {
+    // The source the expression was parsed from. Only the placeholder left
+    // behind by a syntax error has none.
+    pub fn span(&self) -> Option<token::Span> {
+        let span = match self {
+            Expr::Assign(a) => a.name.span.to(a.value.span()?),
+            Expr::Binary(b) => b.left.span()?.to(b.right.span()?),
+            Expr::Call(c) => c.callee.span()?.to(c.paren.span),
+            Expr::Get(g) => g.object.span()?.to(g.name.span),
+            Expr::Grouping(g) => g.span,
+            Expr::Literal(l) => l.span,
+            Expr::Logical(l) => l.left.span()?.to(l.right.span()?),
+            Expr::Set(s) => s.object.span()?.to(s.value.span()?),
+            Expr::Super(s) => s.keyword.span.to(s.method.span),
+            Expr::This(t) => t.keyword.span,
+            Expr::Unary(u) => u.op.span.to(u.right.span()?),
+            Expr::Variable(v) => v.name.span,
+            Expr::None => return None,
+        };
+        Some(span)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Stmt {
+    Block(Box<Block>),
+    Class(Box<Class>),
+    Expression(Box<Expression>),
+    Function(Arc<Function>),
+    If(Box<If>),
+    Print(Box<Print>),
+    Return(Box<Return>),
+    Var(Box<Var>),
+    While(Box<While>),
+}
+
+impl Stmt {
+    pub fn span(&self) -> token::Span {
+        match self {
+            Stmt::Block(b) => b.span,
+            Stmt::Class(c) => c.span,
+            Stmt::Expression(e) => e.span,
+            Stmt::Function(f) => f.span,
+            Stmt::If(i) => i.span,
+            Stmt::Print(p) => p.span,
+            Stmt::Return(r) => r.span,
+            Stmt::Var(v) => v.span,
+            Stmt::While(w) => w.span,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Block {
+    pub statements: Vec<Stmt>,
+    pub span: token::Span,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Class {
+    pub name: token::Token,
+    pub superclass: Option<Variable>,
+    pub methods: Vec<Arc<Function>>,
+    pub span: token::Span,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Expression {
+    pub exp: Expr,
+    pub span: token::Span,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Function {
+    pub name: token::Token,
+    pub params: Vec<token::Token>,
+    pub body: Vec<Stmt>,
+    pub span: token::Span,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct If {
+    pub condition: Expr,
+    pub then_branch: Stmt,
+    pub else_branch: Option<Stmt>,
+    pub span: token::Span,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Print {
+    pub keyword: token::Token,
+    pub exp: Expr,
+    pub span: token::Span,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Return {
+    pub keyword: token::Token,
+    pub value: Option<Expr>,
+    pub span: token::Span,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Var {
+    pub name: token::Token,
+    pub initializer: Option<Expr>,
+    pub span: token::Span,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct While {
+    pub condition: Expr,
+    pub body: Stmt,
+    pub span: token::Span,
+}
@@ -0,0 +1,463 @@
+use super::ast;
+use super::compiler::{CompileError, Compiler};
+use super::diagnostics::Diagnostic;
+use super::interpreter::{Interpreter, RuntimeError};
+use super::limits::Limits;
+use super::parser::Parser;
+use super::resolver::{ResolveError, Resolver};
+use super::scanner::Scanner;
+use super::vm::Vm;
+use std::fmt;
+use std::io::Write;
+
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum Backend {
+    #[default]
+    TreeWalker,
+    Bytecode,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    Resolve(Vec<ResolveError>),
+    Compile(Vec<CompileError>),
+    Runtime(RuntimeError),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Resolve(errors) => {
+                let lines: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+                write!(f, "{}", lines.join("\n"))
+            }
+            Error::Compile(errors) => {
+                let lines: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+                write!(f, "{}", lines.join("\n"))
+            }
+            Error::Runtime(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl Error {
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        match self {
+            Error::Resolve(errors) => errors.iter().map(Diagnostic::from).collect(),
+            Error::Compile(errors) => errors.iter().map(Diagnostic::from).collect(),
+            Error::Runtime(error) => vec![Diagnostic::from(error)],
+        }
+    }
+}
+
+// Scans and parses a program, turning lexical and syntax errors into
+// diagnostics. The statements are still returned when there are errors.
+pub fn parse(source: &str) -> (Vec<ast::Stmt>, Vec<Diagnostic>) {
+    parse_file(source, 0)
+}
+
+// Parses one of several sources run in the same session, such as the lines
+// of a prompt, so that spans say which one they point into.
+pub fn parse_file(source: &str, file: usize) -> (Vec<ast::Stmt>, Vec<Diagnostic>) {
+    let mut scanner = Scanner::with_file(source, file);
+    let tokens = scanner.parse();
+    let mut diagnostics: Vec<Diagnostic> = scanner.errors().iter().map(Diagnostic::from).collect();
+
+    let (statements, errors) = Parser::new(tokens).parse();
+    diagnostics.extend(errors.iter().map(Diagnostic::from));
+    (statements, diagnostics)
+}
+
+// Runs a parsed program on the chosen backend. Both backends share the
+// resolver's static checks and report the same output and runtime errors.
+pub fn run(
+    backend: Backend,
+    statements: &[ast::Stmt],
+    output: Box<dyn Write>,
+) -> Result<(), Error> {
+    Session::new(backend, output).run(statements)
+}
+
+enum Engine {
+    TreeWalker(Interpreter),
+    Bytecode(Vm),
+}
+
+// A backend that keeps its globals between runs, as the prompt needs.
+pub struct Session {
+    engine: Engine,
+}
+
+impl Session {
+    pub fn new(backend: Backend, output: Box<dyn Write>) -> Self {
+        let engine = match backend {
+            Backend::TreeWalker => Engine::TreeWalker(Interpreter::with_output(output)),
+            Backend::Bytecode => Engine::Bytecode(Vm::with_output(output)),
+        };
+        Session { engine }
+    }
+
+    pub fn set_limits(&mut self, limits: Limits) {
+        match &mut self.engine {
+            Engine::TreeWalker(interpreter) => interpreter.set_limits(limits),
+            Engine::Bytecode(vm) => vm.set_limits(limits),
+        }
+    }
+
+    pub fn run(&mut self, statements: &[ast::Stmt]) -> Result<(), Error> {
+        let locals = Resolver::new()
+            .resolve(statements)
+            .map_err(Error::Resolve)?;
+
+        match &mut self.engine {
+            Engine::TreeWalker(interpreter) => {
+                interpreter.resolve(locals);
+                interpreter.interpret(statements).map_err(Error::Runtime)
+            }
+            Engine::Bytecode(vm) => {
+                let function = Compiler::new()
+                    .compile(statements)
+                    .map_err(Error::Compile)?;
+                vm.interpret(function).map_err(Error::Runtime)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::super::interpreter::{OutputBuffer, RuntimeErrorKind};
+    use super::super::limits::Limit;
+    use super::super::parser;
+    use super::super::scanner;
+    use super::*;
+    use std::time::Duration;
+
+    fn run_on(backend: Backend, source: &str) -> (String, Option<String>) {
+        let tokens = scanner::Scanner::new(source).parse();
+        let (statements, errors) = parser::Parser::new(tokens).parse();
+        assert!(errors.is_empty(), "{:?}", errors);
+        let output = OutputBuffer::new();
+        let result = run(backend, &statements, Box::new(output.clone()));
+        (output.contents(), result.err().map(|e| e.to_string()))
+    }
+
+    fn assert_same(source: &str, expected: &str) {
+        let tree = run_on(Backend::TreeWalker, source);
+        let bytecode = run_on(Backend::Bytecode, source);
+        assert_eq!(tree, bytecode);
+        assert_eq!(tree.0, expected);
+    }
+
+    #[test]
+    fn arithmetic_and_strings() {
+        assert_same(
+            r#"print 1 + 2 * 3; print (1 + 2) * 3; print -4 / 2; print "a" + "b"; print !nil;"#,
+            "7\n9\n-2\nab\ntrue\n",
+        );
+    }
+
+    #[test]
+    fn control_flow() {
+        assert_same(
+            r#"
+                var sum = 0;
+                for (var i = 0; i < 5; i = i + 1) {
+                    if (i == 2) sum = sum + 10; else sum = sum + i;
+                }
+                while (sum > 10) sum = sum - 1;
+                print sum;
+                print nil or "default";
+                print false and 1;
+            "#,
+            "10\ndefault\nfalse\n",
+        );
+    }
+
+    #[test]
+    fn closures_and_recursion() {
+        assert_same(
+            r#"
+                fun fib(n) { if (n < 2) return n; return fib(n - 1) + fib(n - 2); }
+                print fib(15);
+                fun counter() {
+                    var i = 0;
+                    fun count() { i = i + 1; return i; }
+                    return count;
+                }
+                var c = counter();
+                c();
+                print c();
+                print fib;
+                print clock;
+            "#,
+            "610\n2\n<fn fib>\n<native fn>\n",
+        );
+    }
+
+    #[test]
+    fn classes_and_inheritance() {
+        assert_same(
+            r#"
+                class A {
+                    init(name) { this.name = name; }
+                    greet() { return "hi " + this.name; }
+                }
+                class B < A {
+                    greet() { return super.greet() + "!"; }
+                }
+                var b = B("bob");
+                print b.greet();
+                print B;
+                print b;
+                b.extra = 3;
+                print b.extra;
+                print b == b;
+                print A("x") == A("x");
+            "#,
+            "hi bob!\nB\nB instance\n3\ntrue\nfalse\n",
+        );
+    }
+
+    #[test]
+    fn runtime_errors_match() {
+        for source in [
+            "print 1;\nprint -\"a\";",
+            "print 1 + nil;",
+            "print 1 < \"a\";",
+            "print missing;",
+            "missing = 1;",
+            "var a = 1;\na();",
+            "fun f(a) {}\nf(1, 2);",
+            "class A {}\nA(1);",
+            "class A {}\nprint A().missing;",
+            "var a = 1;\na.field = 2;",
+            "var a = 1;\nprint a.field;",
+            "var NotClass = 1;\nclass B < NotClass {}",
+        ] {
+            let tree = run_on(Backend::TreeWalker, source);
+            assert!(tree.1.is_some(), "{}", source);
+            assert_eq!(tree, run_on(Backend::Bytecode, source), "{}", source);
+
+            // Both point at the same token.
+            let (statements, _) = parse(source);
+            let diagnostics = |backend| {
+                let result = run(backend, &statements, Box::new(OutputBuffer::new()));
+                result.unwrap_err().diagnostics()
+            };
+            assert_eq!(
+                diagnostics(Backend::TreeWalker),
+                diagnostics(Backend::Bytecode),
+                "{}",
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn deep_recursion_is_an_error() {
+        let source = "fun f(n) {\n  return f(n + 1);\n}\nf(0);";
+        let tree = run_on(Backend::TreeWalker, source);
+        assert_eq!(tree.1, Some("line 2: stack overflow".to_owned()));
+        assert_eq!(tree, run_on(Backend::Bytecode, source));
+
+        // Both backends nest calls equally deep.
+        let source = "fun f(n) { if (n > 1) f(n - 1); }\nf(1024);\nf(1025);";
+        assert_same(source, "");
+        assert_eq!(
+            run_on(Backend::TreeWalker, source).1,
+            Some("line 1: stack overflow".to_owned())
+        );
+    }
+
+    #[test]
+    fn static_errors_are_shared() {
+        let expected = Some("line 1: can't return from top-level code".to_owned());
+        assert_eq!(run_on(Backend::TreeWalker, "return 1;").1, expected);
+        assert_eq!(run_on(Backend::Bytecode, "return 1;").1, expected);
+    }
+
+    #[test]
+    fn sessions_keep_globals() {
+        for backend in [Backend::TreeWalker, Backend::Bytecode] {
+            let output = OutputBuffer::new();
+            let mut session = Session::new(backend, Box::new(output.clone()));
+
+            for source in ["var a = 1;", "fun f() { return a + 1; }", "print f();"] {
+                let (statements, diagnostics) = parse(source);
+                assert!(diagnostics.is_empty());
+                session.run(&statements).unwrap();
+            }
+            assert_eq!(output.contents(), "2\n");
+        }
+    }
+
+    #[test]
+    fn closures_survive_runtime_errors() {
+        for backend in [Backend::TreeWalker, Backend::Bytecode] {
+            let output = OutputBuffer::new();
+            let mut session = Session::new(backend, Box::new(output.clone()));
+
+            let program = [
+                "var g;",
+                "{ var a = 1; var b = 2; var c = 3; var d = 4; \
+                 fun f() { return a + b + c + d; } g = f; print nil.x; }",
+                "print g();",
+            ];
+            let results: Vec<bool> = program
+                .iter()
+                .map(|source| {
+                    let (statements, diagnostics) = parse(source);
+                    assert!(diagnostics.is_empty());
+                    session.run(&statements).is_ok()
+                })
+                .collect();
+            assert_eq!(results, vec![true, false, true]);
+            assert_eq!(output.contents(), "10\n");
+        }
+    }
+
+    fn limited(backend: Backend, limits: &Limits, source: &str) -> Result<(), Error> {
+        let (statements, diagnostics) = parse(source);
+        assert!(diagnostics.is_empty());
+        let mut session = Session::new(backend, Box::new(OutputBuffer::new()));
+        session.set_limits(limits.clone());
+        session.run(&statements)
+    }
+
+    fn exceeded(result: Result<(), Error>) -> Option<Limit> {
+        match result {
+            Err(Error::Runtime(RuntimeError {
+                kind: RuntimeErrorKind::LimitExceeded(limit),
+                ..
+            })) => Some(limit),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn limits_stop_runaway_programs() {
+        for backend in [Backend::TreeWalker, Backend::Bytecode] {
+            let steps = Limits::unlimited().with_max_steps(10_000);
+            assert_eq!(
+                exceeded(limited(backend, &steps, "while (true) {}")),
+                Some(Limit::Steps)
+            );
+
+            let timeout = Limits::unlimited().with_timeout(Duration::from_millis(10));
+            assert_eq!(
+                exceeded(limited(backend, &timeout, "while (true) {}")),
+                Some(Limit::Deadline)
+            );
+
+            let depth = Limits::unlimited().with_max_call_depth(50);
+            assert_eq!(
+                exceeded(limited(
+                    backend,
+                    &depth,
+                    "fun f(n) { return f(n + 1); } f(0);"
+                )),
+                Some(Limit::CallDepth)
+            );
+            assert_eq!(
+                exceeded(limited(
+                    backend,
+                    &depth,
+                    "fun f(n) { if (n > 0) f(n - 1); } f(49);"
+                )),
+                None
+            );
+
+            let heap = Limits::unlimited().with_max_heap(1 << 16);
+            assert_eq!(
+                exceeded(limited(
+                    backend,
+                    &heap,
+                    "var s = \"a\"; while (true) s = s + s;"
+                )),
+                Some(Limit::Heap)
+            );
+            assert_eq!(
+                exceeded(limited(
+                    backend,
+                    &heap,
+                    "class Node { init(next) { this.next = next; } }
+                     var list = nil; while (true) list = Node(list);"
+                )),
+                Some(Limit::Heap)
+            );
+        }
+    }
+
+    #[test]
+    fn call_depth_limits_stay_within_the_stack() {
+        // A limit past what either backend can nest counts as the deepest
+        // one they can.
+        let depth = Limits::unlimited().with_max_call_depth(100_000);
+        for backend in [Backend::TreeWalker, Backend::Bytecode] {
+            assert_eq!(
+                exceeded(limited(
+                    backend,
+                    &depth,
+                    "fun f(n) { return f(n + 1); } f(0);"
+                )),
+                Some(Limit::CallDepth)
+            );
+        }
+    }
+
+    #[test]
+    fn garbage_does_not_count_against_the_heap() {
+        for backend in [Backend::TreeWalker, Backend::Bytecode] {
+            let limits = Limits::unlimited().with_max_heap(1 << 16);
+            let source = "class A {} for (var i = 0; i < 10000; i = i + 1) { var a = A(); }";
+            assert!(limited(backend, &limits, source).is_ok());
+        }
+    }
+
+    #[test]
+    fn strings_kept_in_fields_count_against_the_heap() {
+        let limits = Limits::unlimited().with_max_heap(1 << 16);
+        let source = |field| {
+            format!(
+                "class Node {{ init(next, s) {{ this.next = next; this.s = {}; }} }}
+                 var s = \"x\"; for (var i = 0; i < 12; i = i + 1) s = s + s;
+                 var list = nil; for (var i = 0; i < 20; i = i + 1) list = Node(list, s + \"!\");",
+                field
+            )
+        };
+
+        for backend in [Backend::TreeWalker, Backend::Bytecode] {
+            assert!(limited(backend, &limits, &source("0")).is_ok());
+            assert_eq!(
+                exceeded(limited(backend, &limits, &source("s"))),
+                Some(Limit::Heap)
+            );
+        }
+    }
+
+    #[test]
+    fn limit_errors_report_a_line() {
+        let limits = Limits::unlimited().with_max_steps(100);
+        let source = "var i = 0;\nwhile (true) {\n  i = i + 1;\n}";
+
+        for backend in [Backend::TreeWalker, Backend::Bytecode] {
+            assert_eq!(
+                limited(backend, &limits, source).unwrap_err().to_string(),
+                "line 3: step limit exceeded"
+            );
+        }
+    }
+
+    #[test]
+    fn parse_collects_lexical_and_syntax_errors() {
+        let (statements, diagnostics) = parse("print 1 @;\nvar;\nprint 2;");
+
+        assert_eq!(statements.len(), 2);
+        assert_eq!(
+            diagnostics.iter().map(|d| d.line()).collect::<Vec<_>>(),
+            vec![Some(1), Some(2)]
+        );
+    }
+}
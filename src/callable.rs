@@ -0,0 +1,140 @@
+use super::ast;
+use super::class::Instance;
+use super::environment::Environment;
+use super::interpreter::{Interpreter, RuntimeError, Unwind, Value};
+use super::native::{IntoNative, NativeError, NativeFunction};
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub trait Callable {
+    fn arity(&self) -> usize;
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Value>,
+    ) -> Result<Value, RuntimeError>;
+}
+
+pub struct Function {
+    declaration: Arc<ast::Function>,
+    closure: Rc<RefCell<Environment>>,
+    is_initializer: bool,
+}
+
+impl Function {
+    pub fn new(
+        declaration: Arc<ast::Function>,
+        closure: Rc<RefCell<Environment>>,
+        is_initializer: bool,
+    ) -> Self {
+        Function {
+            declaration,
+            closure,
+            is_initializer,
+        }
+    }
+
+    pub fn bind(&self, instance: Rc<RefCell<Instance>>) -> Function {
+        let mut environment = Environment::with_enclosing(self.closure.clone());
+        environment.define("this", Value::Instance(instance));
+        Function::new(
+            self.declaration.clone(),
+            Rc::new(RefCell::new(environment)),
+            self.is_initializer,
+        )
+    }
+
+    fn this(&self) -> Value {
+        self.closure
+            .borrow()
+            .get_local("this")
+            .unwrap_or(Value::None)
+    }
+}
+
+impl Callable for Function {
+    fn arity(&self) -> usize {
+        self.declaration.params.len()
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Value>,
+    ) -> Result<Value, RuntimeError> {
+        let mut environment = Environment::with_enclosing(self.closure.clone())
+            .tracked(interpreter.allocate::<Environment>());
+        for (param, argument) in self.declaration.params.iter().zip(arguments) {
+            environment.define(param.name(), argument);
+        }
+
+        match interpreter.execute_block(&self.declaration.body, Rc::new(RefCell::new(environment)))
+        {
+            Ok(()) | Err(Unwind::Return(_)) if self.is_initializer => Ok(self.this()),
+            Ok(()) => Ok(Value::None),
+            Err(Unwind::Return(value)) => Ok(value),
+            Err(Unwind::Error(err)) => Err(err),
+        }
+    }
+}
+
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<fn {}>", self.declaration.name.name())
+    }
+}
+
+impl fmt::Debug for Function {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+pub struct Native {
+    pub name: String,
+    pub function: Rc<dyn NativeFunction>,
+}
+
+impl Native {
+    pub fn new(name: &str, function: Rc<dyn NativeFunction>) -> Self {
+        Native {
+            name: name.to_owned(),
+            function,
+        }
+    }
+
+    pub fn arity(&self) -> usize {
+        self.function.arity()
+    }
+
+    pub fn call(&self, arguments: &[Value]) -> Result<Value, NativeError> {
+        self.function.call(arguments)
+    }
+}
+
+impl fmt::Display for Native {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<native fn>")
+    }
+}
+
+impl fmt::Debug for Native {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<native fn {}>", self.name)
+    }
+}
+
+pub fn natives() -> Vec<Native> {
+    vec![Native::new("clock", clock.into_native())]
+}
+
+// Seconds since the Unix epoch, shared by both backends' clock() native.
+pub fn clock() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs_f64())
+        .unwrap_or(0.0)
+}
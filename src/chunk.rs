@@ -0,0 +1,283 @@
+use super::token;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::rc::Rc;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[repr(u8)]
+pub enum OpCode {
+    Constant,
+    Nil,
+    True,
+    False,
+    Pop,
+    GetLocal,
+    SetLocal,
+    GetGlobal,
+    DefineGlobal,
+    SetGlobal,
+    GetUpvalue,
+    SetUpvalue,
+    GetProperty,
+    SetProperty,
+    GetSuper,
+    Equal,
+    NotEqual,
+    Greater,
+    GreaterEqual,
+    Less,
+    LessEqual,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Not,
+    Negate,
+    Print,
+    Jump,
+    JumpIfFalse,
+    Loop,
+    Call,
+    Closure,
+    CloseUpvalue,
+    Return,
+    Class,
+    Inherit,
+    Method,
+}
+
+impl OpCode {
+    const ALL: [OpCode; 38] = [
+        OpCode::Constant,
+        OpCode::Nil,
+        OpCode::True,
+        OpCode::False,
+        OpCode::Pop,
+        OpCode::GetLocal,
+        OpCode::SetLocal,
+        OpCode::GetGlobal,
+        OpCode::DefineGlobal,
+        OpCode::SetGlobal,
+        OpCode::GetUpvalue,
+        OpCode::SetUpvalue,
+        OpCode::GetProperty,
+        OpCode::SetProperty,
+        OpCode::GetSuper,
+        OpCode::Equal,
+        OpCode::NotEqual,
+        OpCode::Greater,
+        OpCode::GreaterEqual,
+        OpCode::Less,
+        OpCode::LessEqual,
+        OpCode::Add,
+        OpCode::Subtract,
+        OpCode::Multiply,
+        OpCode::Divide,
+        OpCode::Not,
+        OpCode::Negate,
+        OpCode::Print,
+        OpCode::Jump,
+        OpCode::JumpIfFalse,
+        OpCode::Loop,
+        OpCode::Call,
+        OpCode::Closure,
+        OpCode::CloseUpvalue,
+        OpCode::Return,
+        OpCode::Class,
+        OpCode::Inherit,
+        OpCode::Method,
+    ];
+
+    pub fn from_byte(byte: u8) -> Option<OpCode> {
+        Self::ALL.get(byte as usize).copied()
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Constant {
+    Num(f64),
+    Str(Rc<str>),
+    Function(Rc<FunctionProto>),
+}
+
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Chunk {
+    pub code: Vec<u8>,
+    pub constants: Vec<Constant>,
+    pub lines: Vec<usize>,
+    // The source of each byte, so runtime errors point where the
+    // tree-walker's do.
+    pub spans: Vec<Option<token::Span>>,
+    // Where each number and string already sits in `constants`.
+    shared: HashMap<Shared, usize>,
+}
+
+// Numbers are keyed by their bits so that 0 and -0 stay apart.
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+enum Shared {
+    Num(u64),
+    Str(Rc<str>),
+}
+
+impl Chunk {
+    pub fn new() -> Self {
+        Chunk::default()
+    }
+
+    pub fn write(&mut self, byte: u8, line: usize, span: Option<token::Span>) {
+        self.code.push(byte);
+        self.lines.push(line);
+        self.spans.push(span);
+    }
+
+    // Numbers and strings are shared so that repeated names and literals
+    // do not grow the constant pool.
+    pub fn add_constant(&mut self, constant: Constant) -> usize {
+        let key = match &constant {
+            Constant::Num(n) => Some(Shared::Num(n.to_bits())),
+            Constant::Str(s) => Some(Shared::Str(s.clone())),
+            Constant::Function(_) => None,
+        };
+        if let Some(&index) = key.as_ref().and_then(|k| self.shared.get(k)) {
+            return index;
+        }
+
+        self.constants.push(constant);
+        let index = self.constants.len() - 1;
+        if let Some(key) = key {
+            self.shared.insert(key, index);
+        }
+        index
+    }
+
+    pub fn disassemble(&self, name: &str) -> String {
+        let mut out = format!("== {} ==\n", name);
+        let mut offset = 0;
+
+        while offset < self.code.len() {
+            offset = self.disassemble_instruction(&mut out, offset);
+        }
+        out
+    }
+
+    fn disassemble_instruction(&self, out: &mut String, offset: usize) -> usize {
+        let _ = write!(out, "{:04} ", offset);
+        if offset > 0 && self.lines[offset] == self.lines[offset - 1] {
+            let _ = write!(out, "   | ");
+        } else {
+            let _ = write!(out, "{:4} ", self.lines[offset]);
+        }
+
+        let op = match OpCode::from_byte(self.code[offset]) {
+            Some(op) => op,
+            None => {
+                let _ = writeln!(out, "unknown opcode {}", self.code[offset]);
+                return offset + 1;
+            }
+        };
+
+        match op {
+            OpCode::Constant
+            | OpCode::GetGlobal
+            | OpCode::DefineGlobal
+            | OpCode::SetGlobal
+            | OpCode::GetProperty
+            | OpCode::SetProperty
+            | OpCode::GetSuper
+            | OpCode::Class
+            | OpCode::Method => {
+                let index = self.read_u16(offset + 1);
+                let _ = writeln!(
+                    out,
+                    "{:<16} {:4} '{}'",
+                    format!("{:?}", op),
+                    index,
+                    self.constant_name(index)
+                );
+                offset + 3
+            }
+            OpCode::GetLocal
+            | OpCode::SetLocal
+            | OpCode::GetUpvalue
+            | OpCode::SetUpvalue
+            | OpCode::Call => {
+                let _ = writeln!(
+                    out,
+                    "{:<16} {:4}",
+                    format!("{:?}", op),
+                    self.code[offset + 1]
+                );
+                offset + 2
+            }
+            OpCode::Jump | OpCode::JumpIfFalse | OpCode::Loop => {
+                let jump = self.read_u16(offset + 1);
+                let target = if op == OpCode::Loop {
+                    offset + 3 - jump
+                } else {
+                    offset + 3 + jump
+                };
+                let _ = writeln!(
+                    out,
+                    "{:<16} {:4} -> {}",
+                    format!("{:?}", op),
+                    offset,
+                    target
+                );
+                offset + 3
+            }
+            OpCode::Closure => {
+                let index = self.read_u16(offset + 1);
+                let _ = writeln!(
+                    out,
+                    "{:<16} {:4} '{}'",
+                    format!("{:?}", op),
+                    index,
+                    self.constant_name(index)
+                );
+
+                let upvalue_count = match &self.constants[index] {
+                    Constant::Function(f) => f.upvalue_count,
+                    _ => 0,
+                };
+                let mut offset = offset + 3;
+                for _ in 0..upvalue_count {
+                    let is_local = self.code[offset] == 1;
+                    let index = self.code[offset + 1];
+                    let _ = writeln!(
+                        out,
+                        "{:04}    |                     {} {}",
+                        offset,
+                        if is_local { "local" } else { "upvalue" },
+                        index
+                    );
+                    offset += 2;
+                }
+                offset
+            }
+            _ => {
+                let _ = writeln!(out, "{:?}", op);
+                offset + 1
+            }
+        }
+    }
+
+    fn read_u16(&self, offset: usize) -> usize {
+        ((self.code[offset] as usize) << 8) | self.code[offset + 1] as usize
+    }
+
+    fn constant_name(&self, index: usize) -> String {
+        match &self.constants[index] {
+            Constant::Num(n) => n.to_string(),
+            Constant::Str(s) => s.to_string(),
+            Constant::Function(f) => format!("<fn {}>", f.name),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct FunctionProto {
+    pub name: String,
+    pub arity: usize,
+    pub upvalue_count: usize,
+    pub chunk: Chunk,
+}
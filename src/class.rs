@@ -0,0 +1,132 @@
+use super::callable::{Callable, Function};
+use super::interpreter::{Interpreter, RuntimeError, Value};
+use super::limits::Allocation;
+use super::token;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::mem;
+use std::rc::Rc;
+
+pub struct Class {
+    pub name: String,
+    superclass: Option<Rc<Class>>,
+    methods: HashMap<String, Rc<Function>>,
+}
+
+impl Class {
+    pub fn new(
+        name: &str,
+        superclass: Option<Rc<Class>>,
+        methods: HashMap<String, Rc<Function>>,
+    ) -> Self {
+        Class {
+            name: name.to_owned(),
+            superclass,
+            methods,
+        }
+    }
+
+    pub fn find_method(&self, name: &str) -> Option<Rc<Function>> {
+        match self.methods.get(name) {
+            Some(method) => Some(method.clone()),
+            None => self.superclass.as_ref()?.find_method(name),
+        }
+    }
+}
+
+impl Callable for Rc<Class> {
+    fn arity(&self) -> usize {
+        self.find_method("init").map_or(0, |init| init.arity())
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Value>,
+    ) -> Result<Value, RuntimeError> {
+        let instance = Instance::new(self.clone()).tracked(interpreter.allocate::<Instance>());
+        let instance = Rc::new(RefCell::new(instance));
+
+        if let Some(init) = self.find_method("init") {
+            init.bind(instance.clone()).call(interpreter, arguments)?;
+        }
+
+        Ok(Value::Instance(instance))
+    }
+}
+
+impl fmt::Display for Class {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+impl fmt::Debug for Class {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<class {}>", self.name)
+    }
+}
+
+pub struct Instance {
+    class: Rc<Class>,
+    fields: HashMap<String, Value>,
+    allocation: Option<Allocation>,
+}
+
+impl Instance {
+    pub fn new(class: Rc<Class>) -> Self {
+        Instance {
+            class,
+            fields: HashMap::new(),
+            allocation: None,
+        }
+    }
+
+    // Counts the instance against the interpreter's heap limit.
+    pub(crate) fn tracked(mut self, allocation: Allocation) -> Self {
+        self.allocation = Some(allocation);
+        self
+    }
+
+    pub fn get(
+        instance: &Rc<RefCell<Instance>>,
+        name: &token::Token,
+    ) -> Result<Value, RuntimeError> {
+        if let Some(value) = instance.borrow().fields.get(name.name()) {
+            return Ok(value.clone());
+        }
+
+        let method = instance.borrow().class.find_method(name.name());
+        match method {
+            Some(method) => Ok(Value::Function(Rc::new(method.bind(instance.clone())))),
+            None => Err(RuntimeError::new(
+                name,
+                &format!("undefined property '{}'", name.name()),
+            )),
+        }
+    }
+
+    pub fn set(&mut self, name: &token::Token, value: Value) {
+        let size = value.size();
+        let (added, removed) = match self.fields.insert(name.name().to_owned(), value) {
+            Some(old) => (size, old.size()),
+            None => (mem::size_of::<String>() + name.name().len() + size, 0),
+        };
+        if let Some(allocation) = &mut self.allocation {
+            allocation.resize(added, removed);
+        }
+    }
+}
+
+impl fmt::Display for Instance {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} instance", self.class.name)
+    }
+}
+
+impl fmt::Debug for Instance {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<{} instance>", self.class.name)
+    }
+}
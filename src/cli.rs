@@ -0,0 +1,183 @@
+use lox_lang::backend::{self, Backend, Session};
+use lox_lang::diagnostics::{Diagnostic, Style};
+use std::fs;
+use std::io;
+use std::io::{BufRead, IsTerminal, Write};
+
+// Exit codes from sysexits.h, as used by jlox.
+pub const EX_OK: i32 = 0;
+pub const EX_USAGE: i32 = 64;
+pub const EX_DATAERR: i32 = 65;
+pub const EX_NOINPUT: i32 = 66;
+pub const EX_SOFTWARE: i32 = 70;
+
+const USAGE: &str = "usage: lox [--vm] | lox run [--vm] <file> | lox gui";
+
+// Runs the command line and returns the process exit code.
+pub fn run(args: &[String]) -> i32 {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+    match args.as_slice() {
+        [] => run_prompt(Backend::TreeWalker),
+        ["--vm"] => run_prompt(Backend::Bytecode),
+        ["run", path] => run_file(path, Backend::TreeWalker),
+        ["run", "--vm", path] | ["run", path, "--vm"] => run_file(path, Backend::Bytecode),
+        _ => {
+            eprintln!("{}", USAGE);
+            EX_USAGE
+        }
+    }
+}
+
+fn run_file(path: &str, backend: Backend) -> i32 {
+    let source = match fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(err) => {
+            eprintln!("error: could not read '{}': {}", path, err);
+            return EX_NOINPUT;
+        }
+    };
+
+    let mut session = Session::new(backend, Box::new(io::stdout()));
+    execute(&mut session, &[source])
+}
+
+fn run_prompt(backend: Backend) -> i32 {
+    let mut session = Session::new(backend, Box::new(io::stdout()));
+    let stdin = io::stdin();
+    // Every line is kept, since functions defined on one can fail on a
+    // later one.
+    let mut lines = Vec::new();
+
+    loop {
+        print!("> ");
+        let _ = io::stdout().flush();
+
+        let mut line = String::new();
+        match stdin.lock().read_line(&mut line) {
+            Ok(0) => return EX_OK,
+            Ok(_) => {
+                lines.push(line);
+                execute(&mut session, &lines);
+            }
+            Err(err) => {
+                eprintln!("error: {}", err);
+                return EX_SOFTWARE;
+            }
+        }
+    }
+}
+
+// Runs the last of the sources in the session, reporting any diagnostics on
+// stderr, and returns the exit code it warrants. The earlier sources were
+// run before and are only needed to show errors in them.
+fn execute(session: &mut Session, sources: &[String]) -> i32 {
+    let file = sources.len() - 1;
+    let (statements, errors) = backend::parse_file(&sources[file], file);
+    if !errors.is_empty() {
+        report(&errors, sources);
+        return EX_DATAERR;
+    }
+
+    match session.run(&statements) {
+        Ok(()) => EX_OK,
+        Err(err) => {
+            let _ = io::stdout().flush();
+            report(&err.diagnostics(), sources);
+            match err {
+                backend::Error::Runtime(_) => EX_SOFTWARE,
+                _ => EX_DATAERR,
+            }
+        }
+    }
+}
+
+fn report(errors: &[Diagnostic], sources: &[String]) {
+    let style = if io::stderr().is_terminal() {
+        Style::Ansi
+    } else {
+        Style::Plain
+    };
+    eprint!("{}", render(errors, sources, style));
+}
+
+// Shows each diagnostic against the source it points into. Those that only
+// know a line are taken to be about the last source.
+fn render(errors: &[Diagnostic], sources: &[String], style: Style) -> String {
+    let last = sources.len() - 1;
+    let rendered: Vec<String> = errors
+        .iter()
+        .map(|d| {
+            let source = sources
+                .get(d.file().unwrap_or(last))
+                .unwrap_or(&sources[last]);
+            d.render(source, style)
+        })
+        .collect();
+    rendered.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use lox_lang::interpreter::OutputBuffer;
+
+    fn exit_code(backend: Backend, source: &str) -> (i32, String) {
+        let output = OutputBuffer::new();
+        let mut session = Session::new(backend, Box::new(output.clone()));
+        (
+            execute(&mut session, &[source.to_owned()]),
+            output.contents(),
+        )
+    }
+
+    #[test]
+    fn usage_errors() {
+        assert_eq!(run(&["bogus".to_owned()]), EX_USAGE);
+        assert_eq!(run(&["run".to_owned()]), EX_USAGE);
+        assert_eq!(
+            run(&["run".to_owned(), "a.lox".to_owned(), "b.lox".to_owned()]),
+            EX_USAGE
+        );
+    }
+
+    #[test]
+    fn unreadable_files() {
+        assert_eq!(
+            run(&["run".to_owned(), "does/not/exist.lox".to_owned()]),
+            EX_NOINPUT
+        );
+    }
+
+    #[test]
+    fn exit_codes() {
+        for backend in [Backend::TreeWalker, Backend::Bytecode] {
+            assert_eq!(exit_code(backend, "print 1;"), (EX_OK, "1\n".to_owned()));
+            assert_eq!(exit_code(backend, "print 1 +;").0, EX_DATAERR);
+            assert_eq!(exit_code(backend, "print @;").0, EX_DATAERR);
+            assert_eq!(exit_code(backend, "return 1;").0, EX_DATAERR);
+            assert_eq!(
+                exit_code(backend, "print 1;\nprint -nil;"),
+                (EX_SOFTWARE, "1\n".to_owned())
+            );
+        }
+    }
+
+    #[test]
+    fn prompt_errors_show_the_line_they_come_from() {
+        let mut session = Session::new(Backend::TreeWalker, Box::new(OutputBuffer::new()));
+        let mut lines = vec!["fun f() { return -nil; }\n".to_owned()];
+        assert_eq!(execute(&mut session, &lines), EX_OK);
+        lines.push("f();\n".to_owned());
+
+        let (statements, _) = backend::parse_file(&lines[1], 1);
+        let err = session.run(&statements).unwrap_err();
+        let rendered = render(&err.diagnostics(), &lines, Style::Plain);
+        assert!(
+            rendered.contains("1 | fun f() { return -nil; }\n  |                  ^"),
+            "{}",
+            rendered
+        );
+    }
+}
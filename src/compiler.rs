@@ -0,0 +1,695 @@
+use super::ast;
+use super::chunk::{Chunk, Constant, FunctionProto, OpCode};
+use super::token;
+use std::fmt;
+use std::rc::Rc;
+
+const MAX_LOCALS: usize = 256;
+const MAX_UPVALUES: usize = 256;
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct CompileError {
+    pub line: usize,
+    pub span: Option<token::Span>,
+    pub message: String,
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+#[derive(PartialEq, Clone, Copy)]
+enum FunctionType {
+    Script,
+    Function,
+    Method,
+    Initializer,
+}
+
+struct Local {
+    name: String,
+    depth: Option<usize>,
+    is_captured: bool,
+}
+
+struct Upvalue {
+    index: u8,
+    is_local: bool,
+}
+
+struct FunctionState {
+    function: FunctionProto,
+    typ: FunctionType,
+    locals: Vec<Local>,
+    upvalues: Vec<Upvalue>,
+    scope_depth: usize,
+}
+
+impl FunctionState {
+    fn new(name: &str, typ: FunctionType) -> Self {
+        let receiver = match typ {
+            FunctionType::Method | FunctionType::Initializer => "this",
+            _ => "",
+        };
+
+        FunctionState {
+            function: FunctionProto {
+                name: name.to_owned(),
+                ..FunctionProto::default()
+            },
+            typ,
+            locals: vec![Local {
+                name: receiver.to_owned(),
+                depth: Some(0),
+                is_captured: false,
+            }],
+            upvalues: Vec::new(),
+            scope_depth: 0,
+        }
+    }
+}
+
+pub struct Compiler {
+    states: Vec<FunctionState>,
+    line: usize,
+    // The token being compiled, for errors.
+    span: Option<token::Span>,
+    errors: Vec<CompileError>,
+}
+
+impl Default for Compiler {
+    fn default() -> Self {
+        Compiler::new()
+    }
+}
+
+impl Compiler {
+    // Public
+    pub fn new() -> Self {
+        Compiler {
+            states: vec![FunctionState::new("", FunctionType::Script)],
+            line: 1,
+            span: None,
+            errors: Vec::new(),
+        }
+    }
+
+    // Expects statements that already passed the resolver, which reports
+    // the static errors shared with the tree-walking interpreter.
+    pub fn compile(
+        mut self,
+        statements: &[ast::Stmt],
+    ) -> Result<Rc<FunctionProto>, Vec<CompileError>> {
+        for s in statements {
+            self.statement(s);
+        }
+        let (function, _) = self.end_function();
+
+        if self.errors.is_empty() {
+            Ok(Rc::new(function))
+        } else {
+            Err(self.errors)
+        }
+    }
+
+    // Statements
+    fn statement(&mut self, s: &ast::Stmt) {
+        match s {
+            ast::Stmt::Block(b) => {
+                self.begin_scope();
+                for s in &b.statements {
+                    self.statement(s);
+                }
+                self.end_scope();
+            }
+            ast::Stmt::Class(c) => self.class_declaration(c),
+            ast::Stmt::Expression(e) => {
+                self.expression(&e.exp);
+                self.emit(OpCode::Pop);
+            }
+            ast::Stmt::Function(f) => {
+                self.at(f.name.span);
+                if self.state().scope_depth > 0 {
+                    self.add_local(f.name.name());
+                    self.mark_initialized();
+                    self.function(f, FunctionType::Function);
+                } else {
+                    self.function(f, FunctionType::Function);
+                    self.emit_constant_op(OpCode::DefineGlobal, Self::name_constant(&f.name));
+                }
+            }
+            ast::Stmt::If(i) => {
+                self.expression(&i.condition);
+                let then_jump = self.emit_jump(OpCode::JumpIfFalse);
+                self.emit(OpCode::Pop);
+                self.statement(&i.then_branch);
+
+                let else_jump = self.emit_jump(OpCode::Jump);
+                self.patch_jump(then_jump);
+                self.emit(OpCode::Pop);
+                if let Some(else_branch) = &i.else_branch {
+                    self.statement(else_branch);
+                }
+                self.patch_jump(else_jump);
+            }
+            ast::Stmt::Print(p) => {
+                self.expression(&p.exp);
+                self.at(p.keyword.span);
+                self.emit(OpCode::Print);
+            }
+            ast::Stmt::Return(r) => {
+                self.at(r.keyword.span);
+                if self.state().typ == FunctionType::Initializer {
+                    self.emit_bytes(OpCode::GetLocal as u8, 0);
+                } else if let Some(value) = &r.value {
+                    self.expression(value);
+                } else {
+                    self.emit(OpCode::Nil);
+                }
+                self.emit(OpCode::Return);
+            }
+            ast::Stmt::Var(v) => {
+                let local = self.state().scope_depth > 0;
+                if local {
+                    self.add_local(v.name.name());
+                }
+
+                match &v.initializer {
+                    Some(initializer) => self.expression(initializer),
+                    None => self.emit(OpCode::Nil),
+                }
+
+                self.at(v.name.span);
+                if local {
+                    self.mark_initialized();
+                } else {
+                    self.emit_constant_op(OpCode::DefineGlobal, Self::name_constant(&v.name));
+                }
+            }
+            ast::Stmt::While(w) => {
+                let loop_start = self.chunk().code.len();
+                self.expression(&w.condition);
+                let exit_jump = self.emit_jump(OpCode::JumpIfFalse);
+                self.emit(OpCode::Pop);
+                self.statement(&w.body);
+                self.emit_loop(loop_start);
+
+                self.patch_jump(exit_jump);
+                self.emit(OpCode::Pop);
+            }
+        }
+    }
+
+    fn class_declaration(&mut self, c: &ast::Class) {
+        self.at(c.name.span);
+        let name = Self::name_constant(&c.name);
+        let local = self.state().scope_depth > 0;
+
+        if local {
+            self.add_local(c.name.name());
+        }
+        self.emit_constant_op(OpCode::Class, name.clone());
+        if local {
+            self.mark_initialized();
+        } else {
+            self.emit_constant_op(OpCode::DefineGlobal, name.clone());
+        }
+
+        if let Some(superclass) = &c.superclass {
+            self.named_variable(superclass.name.name(), superclass.name.span, false);
+
+            self.begin_scope();
+            self.add_local("super");
+            self.mark_initialized();
+
+            self.named_variable(c.name.name(), c.name.span, false);
+            self.at(superclass.name.span);
+            self.emit(OpCode::Inherit);
+        }
+
+        self.named_variable(c.name.name(), c.name.span, false);
+        for method in &c.methods {
+            let typ = if method.name.name() == "init" {
+                FunctionType::Initializer
+            } else {
+                FunctionType::Method
+            };
+            self.function(method, typ);
+            self.emit_constant_op(OpCode::Method, Self::name_constant(&method.name));
+        }
+        self.emit(OpCode::Pop);
+
+        if c.superclass.is_some() {
+            self.end_scope();
+        }
+    }
+
+    fn function(&mut self, f: &ast::Function, typ: FunctionType) {
+        self.states.push(FunctionState::new(f.name.name(), typ));
+        self.begin_scope();
+
+        for param in &f.params {
+            self.state_mut().function.arity += 1;
+            self.add_local(param.name());
+            self.mark_initialized();
+        }
+        for s in &f.body {
+            self.statement(s);
+        }
+
+        let (function, upvalues) = self.end_function();
+        self.at(f.name.span);
+        self.emit_constant_op(OpCode::Closure, Constant::Function(Rc::new(function)));
+        for upvalue in upvalues {
+            self.emit_bytes(upvalue.is_local as u8, upvalue.index);
+        }
+    }
+
+    fn end_function(&mut self) -> (FunctionProto, Vec<Upvalue>) {
+        self.emit_return();
+
+        let state = match self.states.pop() {
+            Some(state) => state,
+            None => return (FunctionProto::default(), Vec::new()),
+        };
+        let mut function = state.function;
+        function.upvalue_count = state.upvalues.len();
+        (function, state.upvalues)
+    }
+
+    // Expressions
+    fn expression(&mut self, e: &ast::Expr) {
+        match e {
+            ast::Expr::Assign(a) => {
+                self.expression(&a.value);
+                self.named_variable(a.name.name(), a.name.span, true);
+            }
+            ast::Expr::Binary(b) => {
+                self.expression(&b.left);
+                self.expression(&b.right);
+                self.at(b.op.span);
+                match b.op.typ {
+                    token::Type::EqualEqual => self.emit(OpCode::Equal),
+                    token::Type::BangEqual => self.emit(OpCode::NotEqual),
+                    token::Type::Greater => self.emit(OpCode::Greater),
+                    token::Type::GreaterEqual => self.emit(OpCode::GreaterEqual),
+                    token::Type::Less => self.emit(OpCode::Less),
+                    token::Type::LessEqual => self.emit(OpCode::LessEqual),
+                    token::Type::Plus => self.emit(OpCode::Add),
+                    token::Type::Minus => self.emit(OpCode::Subtract),
+                    token::Type::Star => self.emit(OpCode::Multiply),
+                    token::Type::Slash => self.emit(OpCode::Divide),
+                    _ => self.error("unknown binary operator"),
+                }
+            }
+            ast::Expr::Call(c) => {
+                self.expression(&c.callee);
+                for argument in &c.arguments {
+                    self.expression(argument);
+                }
+                self.at(c.paren.span);
+                self.emit_bytes(OpCode::Call as u8, c.arguments.len() as u8);
+            }
+            ast::Expr::Get(g) => {
+                self.expression(&g.object);
+                self.at(g.name.span);
+                self.emit_constant_op(OpCode::GetProperty, Self::name_constant(&g.name));
+            }
+            ast::Expr::Grouping(g) => self.expression(&g.exp),
+            ast::Expr::Literal(l) => match &l.value {
+                token::Value::Str(s) => {
+                    self.emit_constant_op(OpCode::Constant, Constant::Str(Rc::from(s.as_str())))
+                }
+                token::Value::Num(n) => self.emit_constant_op(OpCode::Constant, Constant::Num(*n)),
+                token::Value::Bool(true) => self.emit(OpCode::True),
+                token::Value::Bool(false) => self.emit(OpCode::False),
+                token::Value::None => self.emit(OpCode::Nil),
+            },
+            ast::Expr::Logical(l) => {
+                self.expression(&l.left);
+                self.at(l.op.span);
+                if l.op.typ == token::Type::Or {
+                    let else_jump = self.emit_jump(OpCode::JumpIfFalse);
+                    let end_jump = self.emit_jump(OpCode::Jump);
+                    self.patch_jump(else_jump);
+                    self.emit(OpCode::Pop);
+                    self.expression(&l.right);
+                    self.patch_jump(end_jump);
+                } else {
+                    let end_jump = self.emit_jump(OpCode::JumpIfFalse);
+                    self.emit(OpCode::Pop);
+                    self.expression(&l.right);
+                    self.patch_jump(end_jump);
+                }
+            }
+            ast::Expr::Set(s) => {
+                self.expression(&s.object);
+                self.expression(&s.value);
+                self.at(s.name.span);
+                self.emit_constant_op(OpCode::SetProperty, Self::name_constant(&s.name));
+            }
+            ast::Expr::Super(s) => {
+                self.named_variable("this", s.keyword.span, false);
+                self.named_variable("super", s.keyword.span, false);
+                self.at(s.method.span);
+                self.emit_constant_op(OpCode::GetSuper, Self::name_constant(&s.method));
+            }
+            ast::Expr::This(t) => self.named_variable("this", t.keyword.span, false),
+            ast::Expr::Unary(u) => {
+                self.expression(&u.right);
+                self.at(u.op.span);
+                match u.op.typ {
+                    token::Type::Bang => self.emit(OpCode::Not),
+                    token::Type::Minus => self.emit(OpCode::Negate),
+                    _ => self.error("unknown unary operator"),
+                }
+            }
+            ast::Expr::Variable(v) => self.named_variable(v.name.name(), v.name.span, false),
+            ast::Expr::None => self.emit(OpCode::Nil),
+        }
+    }
+
+    // Loads the variable, or stores the value on top of the stack into it
+    // when `assign` is set.
+    fn named_variable(&mut self, name: &str, span: token::Span, assign: bool) {
+        self.at(span);
+        let top = self.states.len() - 1;
+
+        let (get, set, operand) = if let Some(slot) = self.resolve_local(top, name) {
+            (OpCode::GetLocal, OpCode::SetLocal, slot)
+        } else if let Some(index) = self.resolve_upvalue(top, name) {
+            (OpCode::GetUpvalue, OpCode::SetUpvalue, index)
+        } else {
+            let op = if assign {
+                OpCode::SetGlobal
+            } else {
+                OpCode::GetGlobal
+            };
+            self.emit_constant_op(op, Constant::Str(Rc::from(name)));
+            return;
+        };
+
+        let op = if assign { set } else { get };
+        self.emit_bytes(op as u8, operand);
+    }
+
+    fn resolve_local(&mut self, state: usize, name: &str) -> Option<u8> {
+        self.states[state]
+            .locals
+            .iter()
+            .rposition(|local| local.name == name)
+            .map(|slot| slot as u8)
+    }
+
+    fn resolve_upvalue(&mut self, state: usize, name: &str) -> Option<u8> {
+        if state == 0 {
+            return None;
+        }
+
+        if let Some(slot) = self.resolve_local(state - 1, name) {
+            self.states[state - 1].locals[slot as usize].is_captured = true;
+            return Some(self.add_upvalue(state, slot, true));
+        }
+
+        let index = self.resolve_upvalue(state - 1, name)?;
+        Some(self.add_upvalue(state, index, false))
+    }
+
+    fn add_upvalue(&mut self, state: usize, index: u8, is_local: bool) -> u8 {
+        let upvalues = &self.states[state].upvalues;
+        if let Some(existing) = upvalues
+            .iter()
+            .position(|u| u.index == index && u.is_local == is_local)
+        {
+            return existing as u8;
+        }
+
+        if upvalues.len() >= MAX_UPVALUES {
+            self.error("too many closure variables in function");
+            return 0;
+        }
+
+        self.states[state]
+            .upvalues
+            .push(Upvalue { index, is_local });
+        (self.states[state].upvalues.len() - 1) as u8
+    }
+
+    // Scopes
+    fn begin_scope(&mut self) {
+        self.state_mut().scope_depth += 1;
+    }
+
+    fn end_scope(&mut self) {
+        self.state_mut().scope_depth -= 1;
+
+        loop {
+            let state = self.state();
+            let captured = match state.locals.last() {
+                Some(local) if local.depth.is_none_or(|d| d > state.scope_depth) => {
+                    local.is_captured
+                }
+                _ => break,
+            };
+
+            if captured {
+                self.emit(OpCode::CloseUpvalue);
+            } else {
+                self.emit(OpCode::Pop);
+            }
+            self.state_mut().locals.pop();
+        }
+    }
+
+    fn add_local(&mut self, name: &str) {
+        if self.state().locals.len() >= MAX_LOCALS {
+            self.error("too many local variables in function");
+            return;
+        }
+
+        self.state_mut().locals.push(Local {
+            name: name.to_owned(),
+            depth: None,
+            is_captured: false,
+        });
+    }
+
+    fn mark_initialized(&mut self) {
+        let depth = self.state().scope_depth;
+        if let Some(local) = self.state_mut().locals.last_mut() {
+            local.depth = Some(depth);
+        }
+    }
+
+    // Emit
+    fn emit(&mut self, op: OpCode) {
+        let (line, span) = (self.line, self.span);
+        self.chunk_mut().write(op as u8, line, span);
+    }
+
+    fn emit_bytes(&mut self, first: u8, second: u8) {
+        let (line, span) = (self.line, self.span);
+        self.chunk_mut().write(first, line, span);
+        self.chunk_mut().write(second, line, span);
+    }
+
+    fn emit_u16(&mut self, value: u16) {
+        let [high, low] = value.to_be_bytes();
+        self.emit_bytes(high, low);
+    }
+
+    fn emit_constant_op(&mut self, op: OpCode, constant: Constant) {
+        let index = self.chunk_mut().add_constant(constant);
+        if index > u16::MAX as usize {
+            self.error("too many constants in one chunk");
+            return;
+        }
+        self.emit(op);
+        self.emit_u16(index as u16);
+    }
+
+    fn emit_jump(&mut self, op: OpCode) -> usize {
+        self.emit(op);
+        self.emit_u16(u16::MAX);
+        self.chunk().code.len() - 2
+    }
+
+    fn patch_jump(&mut self, offset: usize) {
+        let jump = self.chunk().code.len() - offset - 2;
+        if jump > u16::MAX as usize {
+            self.error("too much code to jump over");
+            return;
+        }
+
+        let [high, low] = (jump as u16).to_be_bytes();
+        self.chunk_mut().code[offset] = high;
+        self.chunk_mut().code[offset + 1] = low;
+    }
+
+    fn emit_loop(&mut self, loop_start: usize) {
+        self.emit(OpCode::Loop);
+
+        let offset = self.chunk().code.len() - loop_start + 2;
+        if offset > u16::MAX as usize {
+            self.error("loop body too large");
+            return;
+        }
+        self.emit_u16(offset as u16);
+    }
+
+    fn emit_return(&mut self) {
+        if self.state().typ == FunctionType::Initializer {
+            self.emit_bytes(OpCode::GetLocal as u8, 0);
+        } else {
+            self.emit(OpCode::Nil);
+        }
+        self.emit(OpCode::Return);
+    }
+
+    // Utilities
+
+    fn state(&self) -> &FunctionState {
+        &self.states[self.states.len() - 1]
+    }
+
+    fn state_mut(&mut self) -> &mut FunctionState {
+        let top = self.states.len() - 1;
+        &mut self.states[top]
+    }
+
+    fn chunk(&self) -> &Chunk {
+        &self.state().function.chunk
+    }
+
+    fn chunk_mut(&mut self) -> &mut Chunk {
+        &mut self.state_mut().function.chunk
+    }
+
+    fn name_constant(name: &token::Token) -> Constant {
+        Constant::Str(Rc::from(name.name()))
+    }
+
+    fn at(&mut self, span: token::Span) {
+        self.line = span.line;
+        self.span = Some(span);
+    }
+
+    fn error(&mut self, message: &str) {
+        self.errors.push(CompileError {
+            line: self.line,
+            span: self.span,
+            message: message.to_owned(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::super::parser;
+    use super::super::scanner;
+    use super::*;
+
+    fn compile(source: &str) -> Result<Rc<FunctionProto>, Vec<CompileError>> {
+        let tokens = scanner::Scanner::new(source).parse();
+        let (statements, errors) = parser::Parser::new(tokens).parse();
+        assert!(errors.is_empty(), "{:?}", errors);
+        Compiler::new().compile(&statements)
+    }
+
+    #[test]
+    fn expression_statement() {
+        let function = compile("print 1 + 2 * 3;").unwrap();
+
+        assert_eq!(
+            function.chunk.disassemble("script"),
+            [
+                "== script ==",
+                "0000    1 Constant            0 '1'",
+                "0003    | Constant            1 '2'",
+                "0006    | Constant            2 '3'",
+                "0009    | Multiply",
+                "0010    | Add",
+                "0011    | Print",
+                "0012    | Nil",
+                "0013    | Return",
+                "",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn locals_and_globals() {
+        let function = compile("var a = 1;\n{\n  var b = a;\n  b = 2;\n}").unwrap();
+
+        assert_eq!(
+            function.chunk.disassemble("script"),
+            [
+                "== script ==",
+                "0000    1 Constant            0 '1'",
+                "0003    | DefineGlobal        1 'a'",
+                "0006    3 GetGlobal           1 'a'",
+                "0009    | Constant            2 '2'",
+                "0012    4 SetLocal            1",
+                "0014    | Pop",
+                "0015    | Pop",
+                "0016    | Nil",
+                "0017    | Return",
+                "",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn closures_capture_upvalues() {
+        let function =
+            compile("fun outer() { var x = 1; fun inner() { return x; } return inner; }").unwrap();
+
+        let Constant::Function(outer) = &function.chunk.constants[0] else {
+            panic!("expected a function constant");
+        };
+        let Constant::Function(inner) = &outer.chunk.constants[1] else {
+            panic!("expected a function constant");
+        };
+        assert_eq!(outer.name, "outer");
+        assert_eq!(outer.upvalue_count, 0);
+        assert_eq!(inner.name, "inner");
+        assert_eq!(inner.upvalue_count, 1);
+        assert!(outer.chunk.disassemble("outer").contains(
+            "0003    | Closure             1 '<fn inner>'\n0006    |                     local 1\n"
+        ));
+    }
+
+    #[test]
+    fn constants_are_shared() {
+        let function = compile("var a = \"s\"; a = \"s\"; print a;").unwrap();
+
+        assert_eq!(function.chunk.constants.len(), 2);
+    }
+
+    #[test]
+    fn too_many_locals() {
+        let declarations: String = (0..256).map(|i| format!("var v{};", i)).collect();
+
+        let source = format!("{{ {} }}", declarations);
+        let span = source.find("v254").map(|start| token::Span {
+            file: 0,
+            start,
+            end: start + 4,
+            line: 1,
+            column: start + 1,
+        });
+        assert_eq!(
+            compile(&source),
+            Err(vec![CompileError {
+                line: 1,
+                span,
+                message: "too many local variables in function".to_owned()
+            }])
+        );
+    }
+}
@@ -0,0 +1,501 @@
+use super::compiler::CompileError;
+use super::interpreter::RuntimeError;
+use super::parser::{ParseError, ParseErrorKind};
+use super::resolver::ResolveError;
+use super::scanner::LexError;
+use super::token;
+use std::fmt;
+use std::fmt::Write;
+use std::ops::Range;
+
+const RED: &str = "\x1b[1;31m";
+const BLUE: &str = "\x1b[1;34m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Style {
+    Plain,
+    Ansi,
+}
+
+// Where a diagnostic points. Errors raised without a token at hand, such as
+// the tree-walker's limit errors, only know their line, so the whole line is
+// underlined for those. Errors from outside any source point nowhere.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Location {
+    Span(token::Span),
+    Line(usize),
+    Unknown,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Label {
+    pub span: token::Span,
+    pub message: String,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Diagnostic {
+    pub message: String,
+    pub location: Location,
+    pub label: Option<String>,
+    pub secondary: Vec<Label>,
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn error(message: &str, span: token::Span) -> Self {
+        Diagnostic::at(message, Location::Span(span))
+    }
+
+    pub fn error_on_line(message: &str, line: usize) -> Self {
+        Diagnostic::at(message, Location::Line(line))
+    }
+
+    pub fn unlocated(message: &str) -> Self {
+        Diagnostic::at(message, Location::Unknown)
+    }
+
+    // The source the diagnostic points into, if it has a span.
+    pub fn file(&self) -> Option<usize> {
+        match self.location {
+            Location::Span(span) => Some(span.file),
+            Location::Line(_) | Location::Unknown => None,
+        }
+    }
+
+    fn at(message: &str, location: Location) -> Self {
+        Diagnostic {
+            message: message.to_owned(),
+            location,
+            label: None,
+            secondary: Vec::new(),
+            notes: Vec::new(),
+        }
+    }
+
+    pub fn with_label(mut self, message: &str) -> Self {
+        self.label = Some(message.to_owned());
+        self
+    }
+
+    pub fn with_secondary(mut self, span: token::Span, message: &str) -> Self {
+        self.secondary.push(Label {
+            span,
+            message: message.to_owned(),
+        });
+        self
+    }
+
+    pub fn with_note(mut self, note: &str) -> Self {
+        self.notes.push(note.to_owned());
+        self
+    }
+
+    pub fn line(&self) -> Option<usize> {
+        match self.location {
+            Location::Span(span) => Some(span.line),
+            Location::Line(line) => Some(line),
+            Location::Unknown => None,
+        }
+    }
+
+    // The bytes of `source` the diagnostic points at: its span, or for a
+    // line location the text of that line without surrounding whitespace.
+    pub fn range(&self, source: &str) -> Option<Range<usize>> {
+        match self.location {
+            Location::Span(span) => {
+                let start = span.start.min(source.len());
+                let end = span.end.clamp(start, source.len());
+                (source.is_char_boundary(start) && source.is_char_boundary(end))
+                    .then_some(start..end)
+            }
+            Location::Line(line) => {
+                let text = source_line(source, line)?;
+                let start: usize = source
+                    .split_inclusive('\n')
+                    .take(line - 1)
+                    .map(str::len)
+                    .sum();
+                let leading = text.len() - text.trim_start().len();
+                Some(start + leading..start + text.trim_end().len().max(leading))
+            }
+            Location::Unknown => None,
+        }
+    }
+
+    pub fn render(&self, source: &str, style: Style) -> String {
+        let paint = |color: &str, text: &str| match style {
+            Style::Plain => text.to_owned(),
+            Style::Ansi => format!("{}{}{}", color, text, RESET),
+        };
+
+        let mut marks = Vec::with_capacity(1 + self.secondary.len());
+        if let Some(mark) = Mark::locate(source, self.location, true, self.label.as_deref()) {
+            marks.push(mark);
+        }
+        for label in &self.secondary {
+            let location = Location::Span(label.span);
+            if let Some(mark) = Mark::locate(source, location, false, Some(&label.message)) {
+                marks.push(mark);
+            }
+        }
+
+        let mut lines: Vec<usize> = marks.iter().map(|m| m.line).collect();
+        lines.sort_unstable();
+        lines.dedup();
+        let width = lines.last().map_or(1, |l| l.to_string().len());
+        let pad = " ".repeat(width);
+        let bar = paint(BLUE, "|");
+
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "{}{}",
+            paint(RED, "error"),
+            paint(BOLD, &format!(": {}", self.message))
+        );
+
+        let column = marks
+            .first()
+            .filter(|m| m.primary)
+            .map_or(1, |m| m.column + 1);
+        if let Some(line) = self.line() {
+            let _ = writeln!(out, "{}{} {}:{}", pad, paint(BLUE, "-->"), line, column);
+        }
+
+        if !lines.is_empty() {
+            let _ = writeln!(out, "{} {}", pad, bar);
+        }
+        for line in lines {
+            let text = source_line(source, line).unwrap_or("");
+            let number = paint(BLUE, &format!("{:>width$}", line, width = width));
+            let _ = writeln!(out, "{} {} {}", number, bar, text);
+
+            for mark in marks.iter().filter(|m| m.line == line) {
+                let underline = if mark.primary {
+                    paint(RED, &format!("^{}", "~".repeat(mark.width - 1)))
+                } else {
+                    paint(BLUE, &"-".repeat(mark.width))
+                };
+                let label = match mark.label {
+                    Some(label) if mark.primary => format!(" {}", paint(RED, label)),
+                    Some(label) => format!(" {}", paint(BLUE, label)),
+                    None => "".to_owned(),
+                };
+                let _ = writeln!(
+                    out,
+                    "{} {} {}{}{}",
+                    pad,
+                    bar,
+                    " ".repeat(mark.column),
+                    underline,
+                    label
+                );
+            }
+        }
+
+        for note in &self.notes {
+            let _ = writeln!(
+                out,
+                "{} {} {}",
+                pad,
+                paint(BLUE, "="),
+                paint(BOLD, "note:") + " " + note
+            );
+        }
+        out
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.line() {
+            Some(line) => write!(f, "line {}: {}", line, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+// An underline on one source line, measured in characters.
+struct Mark<'a> {
+    line: usize,
+    column: usize,
+    width: usize,
+    primary: bool,
+    label: Option<&'a str>,
+}
+
+impl<'a> Mark<'a> {
+    fn locate(
+        source: &str,
+        location: Location,
+        primary: bool,
+        label: Option<&'a str>,
+    ) -> Option<Mark<'a>> {
+        let (line, column, width) = match location {
+            Location::Span(span) => {
+                let start = span.start.min(source.len());
+                if !source.is_char_boundary(start) {
+                    return None;
+                }
+                let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+                let line_end = source[start..]
+                    .find('\n')
+                    .map_or(source.len(), |i| start + i);
+                let end = span.end.clamp(start, line_end);
+                if !source.is_char_boundary(end) {
+                    return None;
+                }
+
+                let column = source[line_start..start].chars().count();
+                let width = source[start..end].trim_end_matches('\r').chars().count();
+                (span.line, column, width)
+            }
+            Location::Line(line) => {
+                let text = source_line(source, line)?;
+                let trimmed = text.trim_start();
+                let column = text.chars().count() - trimmed.chars().count();
+                (line, column, trimmed.trim_end().chars().count())
+            }
+            Location::Unknown => return None,
+        };
+
+        Some(Mark {
+            line,
+            column,
+            width: width.max(1),
+            primary,
+            label,
+        })
+    }
+}
+
+fn source_line(source: &str, line: usize) -> Option<&str> {
+    source
+        .split('\n')
+        .nth(line.checked_sub(1)?)
+        .map(|l| l.trim_end_matches('\r'))
+}
+
+pub fn render_all(diagnostics: &[Diagnostic], source: &str, style: Style) -> String {
+    let rendered: Vec<String> = diagnostics
+        .iter()
+        .map(|d| d.render(source, style))
+        .collect();
+    rendered.join("\n")
+}
+
+impl From<&LexError> for Diagnostic {
+    fn from(error: &LexError) -> Self {
+        Diagnostic::error(&error.kind.to_string(), error.span)
+    }
+}
+
+impl From<&ParseError> for Diagnostic {
+    fn from(error: &ParseError) -> Self {
+        let diagnostic = Diagnostic::error(&error.message, error.span);
+
+        match error.kind {
+            ParseErrorKind::MissingToken | ParseErrorKind::ExpectedExpression => {
+                let found = match error.token.typ {
+                    token::Type::EndOfFile => "end of file".to_owned(),
+                    _ => format!("'{}'", error.token.lexeme),
+                };
+                let diagnostic = diagnostic.with_label(&format!("found {}", found));
+
+                if error.expected.len() > 1 {
+                    let expected: Vec<&str> = error.expected.iter().map(|t| t.describe()).collect();
+                    diagnostic.with_note(&format!("expected one of {}", expected.join(", ")))
+                } else {
+                    diagnostic
+                }
+            }
+            _ => diagnostic,
+        }
+    }
+}
+
+impl From<&ResolveError> for Diagnostic {
+    fn from(error: &ResolveError) -> Self {
+        Diagnostic {
+            secondary: error.secondary.clone(),
+            notes: error.notes.clone(),
+            ..Diagnostic::error(&error.message, error.span)
+        }
+    }
+}
+
+impl From<&CompileError> for Diagnostic {
+    fn from(error: &CompileError) -> Self {
+        match error.span {
+            Some(span) => Diagnostic::error(&error.message, span),
+            None => Diagnostic::error_on_line(&error.message, error.line),
+        }
+    }
+}
+
+impl From<&RuntimeError> for Diagnostic {
+    fn from(error: &RuntimeError) -> Self {
+        match (error.span, error.line) {
+            (Some(span), _) => Diagnostic::error(&error.message, span),
+            (None, Some(line)) => Diagnostic::error_on_line(&error.message, line),
+            (None, None) => Diagnostic::unlocated(&error.message),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::super::parser;
+    use super::super::scanner;
+    use super::*;
+
+    fn span(start: usize, end: usize, line: usize, column: usize) -> token::Span {
+        token::Span {
+            file: 0,
+            start,
+            end,
+            line,
+            column,
+        }
+    }
+
+    #[test]
+    fn underlines_the_primary_span() {
+        let source = "var a = 1;\nprint a + nope;";
+        let diagnostic =
+            Diagnostic::error("undefined variable 'nope'", span(21, 25, 2, 11)).with_label("here");
+
+        assert_eq!(
+            diagnostic.render(source, Style::Plain),
+            [
+                "error: undefined variable 'nope'",
+                " --> 2:11",
+                "  |",
+                "2 | print a + nope;",
+                "  |           ^~~~ here",
+                "",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn secondary_labels_and_notes() {
+        let source = "{\n  var a = 1;\n  var a = 2;\n}";
+        let diagnostic = Diagnostic::error("already a variable with this name", span(21, 22, 3, 7))
+            .with_secondary(span(8, 9, 2, 7), "first declared here")
+            .with_note("shadowing is only allowed in a nested scope");
+
+        assert_eq!(
+            diagnostic.render(source, Style::Plain),
+            [
+                "error: already a variable with this name",
+                " --> 3:7",
+                "  |",
+                "2 |   var a = 1;",
+                "  |       - first declared here",
+                "3 |   var a = 2;",
+                "  |       ^",
+                "  = note: shadowing is only allowed in a nested scope",
+                "",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn line_locations_underline_the_whole_line() {
+        let diagnostic = Diagnostic::error_on_line("operands must be numbers", 1);
+
+        assert_eq!(
+            diagnostic.render("  print 1 - nil;", Style::Plain),
+            [
+                "error: operands must be numbers",
+                " --> 1:3",
+                "  |",
+                "1 |   print 1 - nil;",
+                "  |   ^~~~~~~~~~~~~~",
+                "",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn unlocated_errors_show_only_the_message() {
+        let diagnostic = Diagnostic::from(&RuntimeError::unlocated("undefined variable 'f'"));
+
+        assert_eq!(diagnostic.to_string(), "undefined variable 'f'");
+        assert_eq!(diagnostic.range("f();"), None);
+        assert_eq!(
+            diagnostic.render("f();", Style::Plain),
+            "error: undefined variable 'f'\n"
+        );
+    }
+
+    #[test]
+    fn columns_count_characters() {
+        let source = "print \"é\" + @;";
+        let mut scanner = scanner::Scanner::new(source);
+        scanner.parse();
+        let diagnostic = Diagnostic::from(&scanner.errors()[0]);
+
+        assert!(diagnostic
+            .render(source, Style::Plain)
+            .ends_with("1 | print \"é\" + @;\n  |             ^\n"));
+    }
+
+    #[test]
+    fn parse_errors_point_at_the_offending_token() {
+        let source = "print (1 + ;";
+        let tokens = scanner::Scanner::new(source).parse();
+        let (_, errors) = parser::Parser::new(tokens).parse();
+        let rendered = Diagnostic::from(&errors[0]).render(source, Style::Plain);
+
+        assert!(rendered.starts_with("error: expect expression\n --> 1:12\n"));
+        assert!(rendered.contains("  |            ^ found ';'\n"));
+        assert!(rendered.contains("= note: expected one of number, string,"));
+    }
+
+    #[test]
+    fn missing_token_at_end_of_file() {
+        let source = "print 1";
+        let tokens = scanner::Scanner::new(source).parse();
+        let (_, errors) = parser::Parser::new(tokens).parse();
+        let rendered = Diagnostic::from(&errors[0]).render(source, Style::Plain);
+
+        assert!(rendered.contains("1 | print 1\n  |        ^ found end of file\n"));
+    }
+
+    #[test]
+    fn ranges() {
+        let source = "var a;\n  print -nil;  \n";
+
+        assert_eq!(
+            Diagnostic::error("bad", span(4, 5, 1, 5)).range(source),
+            Some(4..5)
+        );
+        assert_eq!(
+            Diagnostic::error("eof", span(99, 99, 3, 1)).range(source),
+            Some(source.len()..source.len())
+        );
+        let line = Diagnostic::error_on_line("operand must be a number", 2)
+            .range(source)
+            .unwrap();
+        assert_eq!(&source[line], "print -nil;");
+        assert_eq!(Diagnostic::error_on_line("gone", 9).range(source), None);
+    }
+
+    #[test]
+    fn ansi_output_is_colored() {
+        let diagnostic = Diagnostic::error("bad", span(0, 3, 1, 1));
+        let rendered = diagnostic.render("bad;", Style::Ansi);
+
+        assert!(rendered.starts_with("\x1b[1;31merror\x1b[0m"));
+        assert!(rendered.contains("\x1b[1;31m^~~\x1b[0m"));
+    }
+}
@@ -0,0 +1,133 @@
+use super::interpreter::{RuntimeError, Value};
+use super::limits::Allocation;
+use super::token;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::mem;
+use std::rc::Rc;
+
+#[derive(Debug, Default)]
+pub struct Environment {
+    values: HashMap<String, Value>,
+    enclosing: Option<Rc<RefCell<Environment>>>,
+    allocation: Option<Allocation>,
+}
+
+impl Environment {
+    pub fn new() -> Self {
+        Environment::default()
+    }
+
+    pub fn with_enclosing(enclosing: Rc<RefCell<Environment>>) -> Self {
+        Environment {
+            values: HashMap::new(),
+            enclosing: Some(enclosing),
+            allocation: None,
+        }
+    }
+
+    // Counts the environment against the interpreter's heap limit.
+    pub(crate) fn tracked(mut self, allocation: Allocation) -> Self {
+        self.allocation = Some(allocation);
+        self
+    }
+
+    pub fn define(&mut self, name: &str, value: Value) {
+        let added = mem::size_of::<String>() + name.len() + value.size();
+        let removed = match self.values.insert(name.to_owned(), value) {
+            Some(old) => mem::size_of::<String>() + name.len() + old.size(),
+            None => 0,
+        };
+        self.resize(added, removed);
+    }
+
+    pub fn get_local(&self, name: &str) -> Option<Value> {
+        self.values.get(name).cloned()
+    }
+
+    pub fn get(&self, name: &token::Token) -> Result<Value, RuntimeError> {
+        if let Some(v) = self.values.get(name.name()) {
+            Ok(v.clone())
+        } else if let Some(enclosing) = &self.enclosing {
+            enclosing.borrow().get(name)
+        } else {
+            Err(Self::undefined(name))
+        }
+    }
+
+    pub fn assign(&mut self, name: &token::Token, value: Value) -> Result<(), RuntimeError> {
+        if self.values.contains_key(name.name()) {
+            self.replace(name.name(), value);
+            Ok(())
+        } else if let Some(enclosing) = &self.enclosing {
+            enclosing.borrow_mut().assign(name, value)
+        } else {
+            Err(Self::undefined(name))
+        }
+    }
+
+    pub fn get_at(
+        environment: &Rc<RefCell<Environment>>,
+        distance: usize,
+        name: &token::Token,
+    ) -> Result<Value, RuntimeError> {
+        Self::ancestor(environment, distance)
+            .borrow()
+            .values
+            .get(name.name())
+            .cloned()
+            .ok_or_else(|| Self::undefined(name))
+    }
+
+    pub fn assign_at(
+        environment: &Rc<RefCell<Environment>>,
+        distance: usize,
+        name: &token::Token,
+        value: Value,
+    ) -> Result<(), RuntimeError> {
+        let ancestor = Self::ancestor(environment, distance);
+        let mut ancestor = ancestor.borrow_mut();
+        if ancestor.values.contains_key(name.name()) {
+            ancestor.replace(name.name(), value);
+            Ok(())
+        } else {
+            Err(Self::undefined(name))
+        }
+    }
+
+    pub fn ancestor(
+        environment: &Rc<RefCell<Environment>>,
+        distance: usize,
+    ) -> Rc<RefCell<Environment>> {
+        let mut current = environment.clone();
+        for _ in 0..distance {
+            let enclosing = match &current.borrow().enclosing {
+                Some(enclosing) => enclosing.clone(),
+                None => break,
+            };
+            current = enclosing;
+        }
+        current
+    }
+
+    // Overwrites a variable that is known to be defined here.
+    fn replace(&mut self, name: &str, value: Value) {
+        let added = value.size();
+        let removed = self
+            .values
+            .get_mut(name)
+            .map_or(0, |v| mem::replace(v, value).size());
+        self.resize(added, removed);
+    }
+
+    // Keeps a tracked environment's allocation in step with its variables.
+    fn resize(&mut self, added: usize, removed: usize) {
+        if let Some(allocation) = &mut self.allocation {
+            allocation.resize(added, removed);
+        }
+    }
+
+    fn undefined(name: &token::Token) -> RuntimeError {
+        RuntimeError::new(name, &format!("undefined variable '{}'", name.name()))
+    }
+}
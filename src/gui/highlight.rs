@@ -0,0 +1,173 @@
+use eframe::egui;
+use egui::text::{LayoutJob, LayoutSection};
+use egui::util::cache::{ComputerMut, FrameCache};
+use egui::{Color32, FontId, Galley, TextFormat};
+use lox_lang::scanner::Scanner;
+use lox_lang::token::{self, Category};
+use std::ops::Range;
+use std::sync::Arc;
+
+// The editor's colors, one per kind of source text.
+#[derive(Debug, PartialEq, Clone, Copy, Hash)]
+pub struct Theme {
+    pub keyword: Color32,
+    pub literal: Color32,
+    pub identifier: Color32,
+    pub operator: Color32,
+    pub punctuation: Color32,
+    pub comment: Color32,
+    pub plain: Color32,
+}
+
+impl Theme {
+    pub fn dark() -> Self {
+        Theme {
+            keyword: Color32::from_rgb(198, 120, 221),
+            literal: Color32::from_rgb(152, 195, 121),
+            identifier: Color32::from_rgb(224, 108, 117),
+            operator: Color32::from_rgb(86, 182, 194),
+            punctuation: Color32::from_rgb(171, 178, 191),
+            comment: Color32::from_rgb(127, 132, 142),
+            plain: Color32::from_rgb(171, 178, 191),
+        }
+    }
+
+    pub fn light() -> Self {
+        Theme {
+            keyword: Color32::from_rgb(166, 38, 164),
+            literal: Color32::from_rgb(80, 161, 79),
+            identifier: Color32::from_rgb(228, 86, 73),
+            operator: Color32::from_rgb(1, 132, 188),
+            punctuation: Color32::from_rgb(56, 58, 66),
+            comment: Color32::from_rgb(160, 161, 167),
+            plain: Color32::from_rgb(56, 58, 66),
+        }
+    }
+
+    pub fn from_visuals(visuals: &egui::Visuals) -> Self {
+        if visuals.dark_mode {
+            Theme::dark()
+        } else {
+            Theme::light()
+        }
+    }
+
+    fn color(&self, category: Category) -> Color32 {
+        match category {
+            Category::Keyword => self.keyword,
+            Category::Literal => self.literal,
+            Category::Identifier => self.identifier,
+            Category::Operator => self.operator,
+            Category::Punctuation => self.punctuation,
+            Category::EndOfFile => self.plain,
+        }
+    }
+}
+
+// A `TextEdit` layouter that colors Lox source. Layouts are cached by text,
+// so the source is only rescanned when it changes.
+pub fn layout(ui: &egui::Ui, theme: &Theme, text: &str, wrap_width: f32) -> Arc<Galley> {
+    let font = egui::TextStyle::Monospace.resolve(ui.style());
+    let mut job = ui.memory_mut(|memory| {
+        memory
+            .caches
+            .cache::<HighlightCache>()
+            .get((theme, &font, text))
+    });
+    job.wrap.max_width = wrap_width;
+    ui.fonts(|fonts| fonts.layout_job(job))
+}
+
+type HighlightCache = FrameCache<LayoutJob, Highlighter>;
+
+#[derive(Default)]
+struct Highlighter;
+
+impl ComputerMut<(&Theme, &FontId, &str), LayoutJob> for Highlighter {
+    fn compute(&mut self, (theme, font, text): (&Theme, &FontId, &str)) -> LayoutJob {
+        let mut job = LayoutJob {
+            text: text.to_owned(),
+            ..Default::default()
+        };
+        let mut format = |range: Range<usize>, color: Color32| {
+            job.sections.push(LayoutSection {
+                leading_space: 0.0,
+                byte_range: range,
+                format: TextFormat::simple(font.clone(), color),
+            });
+        };
+
+        let mut cursor = 0;
+        for (span, color) in highlights(theme, text) {
+            if span.start > cursor {
+                format(cursor..span.start, theme.plain);
+            }
+            format(span.start..span.end, color);
+            cursor = span.end;
+        }
+        if cursor < text.len() {
+            format(cursor..text.len(), theme.plain);
+        }
+        job
+    }
+}
+
+// The colored spans of `text` in source order. Anything between them, such
+// as whitespace or unexpected characters, is left plain.
+fn highlights(theme: &Theme, text: &str) -> Vec<(token::Span, Color32)> {
+    let mut scanner = Scanner::new(text);
+    let tokens = scanner.parse();
+
+    let mut spans: Vec<(token::Span, Color32)> = tokens
+        .iter()
+        .filter(|t| !t.span.is_empty())
+        .map(|t| (t.span, theme.color(t.typ.category())))
+        .chain(scanner.comments().iter().map(|c| (*c, theme.comment)))
+        .collect();
+    spans.sort_by_key(|(span, _)| span.start);
+    spans
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn tokens_and_comments_are_colored_in_order() {
+        let theme = Theme::dark();
+        let text = "var a = \"s\"; // note\nprint a;";
+        let colored: Vec<(&str, Color32)> = highlights(&theme, text)
+            .into_iter()
+            .map(|(span, color)| (&text[span.start..span.end], color))
+            .collect();
+
+        assert_eq!(
+            colored,
+            vec![
+                ("var", theme.keyword),
+                ("a", theme.identifier),
+                ("=", theme.operator),
+                ("\"s\"", theme.literal),
+                (";", theme.punctuation),
+                ("// note", theme.comment),
+                ("print", theme.keyword),
+                ("a", theme.identifier),
+                (";", theme.punctuation),
+            ]
+        );
+    }
+
+    #[test]
+    fn layout_covers_the_whole_text() {
+        let text = "print @ 1; // done";
+        let job = Highlighter.compute((&Theme::light(), &FontId::monospace(12.0), text));
+
+        let mut cursor = 0;
+        for section in &job.sections {
+            assert_eq!(section.byte_range.start, cursor);
+            cursor = section.byte_range.end;
+        }
+        assert_eq!(cursor, text.len());
+    }
+}
@@ -0,0 +1,445 @@
+mod highlight;
+mod problems;
+mod tokens;
+mod tree;
+
+use eframe::egui;
+use egui::text::{CCursor, CCursorRange};
+use lox_lang::backend::{self, Backend};
+use lox_lang::diagnostics::{self, Diagnostic};
+use lox_lang::limits::{Cancel, Limits};
+use lox_lang::{ast, interpreter, parser, scanner, token};
+use std::ops::Range;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// How long the buffer has to stay unchanged before it is analysed again.
+const DEBOUNCE: Duration = Duration::from_millis(250);
+
+// Programs always run on a worker thread. Buffers at least this long are
+// scanned and parsed there too, so that typing stays responsive.
+const WORKER_THRESHOLD: usize = 32 * 1024;
+
+// How often a running worker is polled for its result.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+// Programs typed into the editor run on every change, so a runaway one must
+// not hang the window.
+fn limits() -> Limits {
+    Limits::unlimited()
+        .with_max_steps(10_000_000)
+        .with_max_call_depth(256)
+        .with_max_heap(64 << 20)
+        .with_timeout(Duration::from_secs(2))
+}
+
+pub fn run() -> Result<(), eframe::Error> {
+    let options = eframe::NativeOptions {
+        initial_window_size: Some(egui::vec2(1280.0, 800.0)),
+        ..Default::default()
+    };
+
+    eframe::run_native(
+        "Lox Language",
+        options,
+        Box::new(|_cc| Box::new(App::new())),
+    )
+}
+
+// Everything derived from one version of the editor buffer. The output is
+// `None` until the program has run, which happens apart from scanning and
+// parsing because it can take as long as the limits allow.
+#[derive(Clone)]
+struct Analysis {
+    source: String,
+    tokens: Vec<token::Token>,
+    statements: Vec<ast::Stmt>,
+    diagnostics: Vec<Diagnostic>,
+    output: Option<String>,
+}
+
+impl Analysis {
+    fn new(source: &str) -> Self {
+        let mut scanner = scanner::Scanner::new(source);
+        let tokens = scanner.parse();
+        let mut diagnostics: Vec<Diagnostic> =
+            scanner.errors().iter().map(Diagnostic::from).collect();
+
+        let (statements, errors) = parser::Parser::new(tokens.clone()).parse();
+        diagnostics.extend(errors.iter().map(Diagnostic::from));
+
+        // Programs with static errors are not run.
+        let output = (!diagnostics.is_empty())
+            .then(|| diagnostics::render_all(&diagnostics, source, diagnostics::Style::Plain));
+
+        Analysis {
+            source: source.to_owned(),
+            tokens,
+            statements,
+            diagnostics,
+            output,
+        }
+    }
+
+    fn run(&mut self, selected: Backend, limits: Limits) {
+        if self.output.is_some() {
+            return;
+        }
+
+        let buffer = interpreter::OutputBuffer::new();
+        let mut session = backend::Session::new(selected, Box::new(buffer.clone()));
+        session.set_limits(limits);
+        let output = match session.run(&self.statements) {
+            Ok(()) => buffer.contents(),
+            Err(err) => {
+                self.diagnostics.extend(err.diagnostics());
+                buffer.contents()
+                    + &diagnostics::render_all(
+                        &self.diagnostics,
+                        &self.source,
+                        diagnostics::Style::Plain,
+                    )
+            }
+        };
+        self.output = Some(output);
+    }
+}
+
+// A run going on in the background. Dropping it, as happens when a newer
+// buffer replaces it, stops the run.
+struct Worker {
+    receiver: mpsc::Receiver<Analysis>,
+    cancel: Cancel,
+}
+
+impl Drop for Worker {
+    fn drop(&mut self) {
+        self.cancel.cancel();
+    }
+}
+
+struct App {
+    source: String,
+    selected: Backend,
+    analysis: Analysis,
+    // When to redo the analysis after the buffer or backend changed.
+    due: Option<Instant>,
+    worker: Option<Worker>,
+    // The editor's selection as of the last frame, in bytes, and the path of
+    // the syntax tree node it picked out.
+    cursor: Option<Range<usize>>,
+    node: Vec<usize>,
+    reveal: bool,
+    tokens: tokens::Table,
+}
+
+impl App {
+    fn new() -> Self {
+        let selected = Backend::default();
+        let mut analysis = Analysis::new("");
+        analysis.run(selected, limits());
+        App {
+            source: String::new(),
+            selected,
+            analysis,
+            due: None,
+            worker: None,
+            cursor: None,
+            node: Vec::new(),
+            reveal: false,
+            tokens: tokens::Table::default(),
+        }
+    }
+
+    // Paths into the old tree mean nothing in the new one, so the cursor is
+    // looked up again.
+    fn replace(&mut self, analysis: Analysis) {
+        self.analysis = analysis;
+        self.cursor = None;
+        self.node.clear();
+    }
+
+    // Picks up a finished run and starts the next analysis once the buffer
+    // has been left alone for long enough.
+    fn refresh(&mut self, ctx: &egui::Context) {
+        if let Some(worker) = &self.worker {
+            match worker.receiver.try_recv() {
+                Ok(analysis) => {
+                    self.worker = None;
+                    self.finish(analysis);
+                }
+                Err(mpsc::TryRecvError::Empty) => ctx.request_repaint_after(POLL_INTERVAL),
+                Err(mpsc::TryRecvError::Disconnected) => self.worker = None,
+            }
+        }
+
+        let Some(due) = self.due else {
+            return;
+        };
+        let now = Instant::now();
+        if now < due {
+            ctx.request_repaint_after(due - now);
+            return;
+        }
+        self.due = None;
+
+        // Small buffers are scanned and parsed right away so that the panels
+        // keep up with typing, but running the program always happens on the
+        // worker. A run still going for an older buffer is cancelled.
+        let parsed = if self.source.len() < WORKER_THRESHOLD {
+            let analysis = Analysis::new(&self.source);
+            self.replace(analysis.clone());
+            if self.analysis.output.is_some() {
+                self.worker = None;
+                return;
+            }
+            Some(analysis)
+        } else {
+            None
+        };
+
+        self.worker = None;
+        let (sender, receiver) = mpsc::channel();
+        let cancel = Cancel::new();
+        let bounds = limits().with_cancel(cancel.clone());
+        let source = self.source.clone();
+        let selected = self.selected;
+        let spawned = thread::Builder::new()
+            .name("analysis".to_owned())
+            .spawn(move || {
+                let mut analysis = parsed.unwrap_or_else(|| Analysis::new(&source));
+                analysis.run(selected, bounds);
+                let _ = sender.send(analysis);
+            });
+        match spawned {
+            Ok(_) => {
+                self.worker = Some(Worker { receiver, cancel });
+                ctx.request_repaint_after(POLL_INTERVAL);
+            }
+            Err(_) => {
+                let mut analysis = Analysis::new(&self.source);
+                analysis.run(self.selected, limits());
+                self.finish(analysis);
+            }
+        }
+    }
+
+    // A run of the buffer that is already shown only adds its output, which
+    // keeps the place in the syntax tree.
+    fn finish(&mut self, analysis: Analysis) {
+        if analysis.source == self.analysis.source && self.analysis.output.is_none() {
+            self.analysis.output = analysis.output;
+            self.analysis.diagnostics = analysis.diagnostics;
+        } else {
+            self.replace(analysis);
+        }
+    }
+}
+
+impl App {
+    fn editor(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.heading("Editor");
+            if self.worker.is_some() || self.due.is_some() {
+                ui.spinner();
+            }
+        });
+
+        let theme = highlight::Theme::from_visuals(ui.visuals());
+        let mut layouter = |ui: &egui::Ui, text: &str, wrap_width: f32| {
+            highlight::layout(ui, &theme, text, wrap_width)
+        };
+        let output = egui::TextEdit::multiline(&mut self.source)
+            .id(editor_id())
+            .code_editor()
+            .desired_width(f32::INFINITY)
+            .desired_rows(14)
+            .layouter(&mut layouter)
+            .show(ui);
+        if output.response.changed() {
+            self.due = Some(Instant::now() + DEBOUNCE);
+        }
+
+        // Spans are only meaningful for the text they were computed from.
+        if self.analysis.source == self.source {
+            problems::show(ui, &output, &self.source, &self.analysis.diagnostics);
+
+            let cursor = output.cursor_range.map(|range| {
+                let chars = range.as_sorted_char_range();
+                problems::byte_offset(&self.source, chars.start)
+                    ..problems::byte_offset(&self.source, chars.end)
+            });
+            if cursor.is_some() && cursor != self.cursor {
+                self.node = tree::innermost(&self.analysis.statements, cursor.clone().unwrap());
+                self.cursor = cursor;
+                self.reveal = true;
+            }
+        }
+    }
+
+    fn problems(&mut self, ui: &mut egui::Ui) {
+        let diagnostics = &self.analysis.diagnostics;
+        ui.heading(format!("Problems ({})", diagnostics.len()));
+        if let Some(range) = problems::list(ui, &self.analysis.source, diagnostics) {
+            select(ui.ctx(), &self.analysis.source, range);
+        }
+    }
+
+    fn tree(&mut self, ui: &mut egui::Ui) {
+        let analysis = &self.analysis;
+        ui.heading(format!(
+            "Abstract Syntax Tree ({} statements)",
+            analysis.statements.len()
+        ));
+
+        let focus = tree::Focus {
+            selected: &self.node,
+            reveal: self.reveal,
+        };
+        let clicked = egui::ScrollArea::vertical()
+            .id_source("ast")
+            .max_height(220.0)
+            .auto_shrink([false, true])
+            .show(ui, |ui| tree::show(ui, &analysis.statements, &focus))
+            .inner;
+        self.reveal = false;
+
+        let Some(path) = clicked else {
+            return;
+        };
+        if let Some(span) = tree::find(&analysis.statements, &path).and_then(|n| n.span()) {
+            let range = span.start..span.end;
+            select(ui.ctx(), &analysis.source, range.clone());
+            // Keeps the new selection from revealing a different node with
+            // the same span.
+            self.cursor = Some(range);
+        }
+        self.node = path;
+    }
+}
+
+fn editor_id() -> egui::Id {
+    egui::Id::new("editor")
+}
+
+// Selects a byte range of the editor's text and focuses the editor.
+fn select(ctx: &egui::Context, source: &str, range: Range<usize>) {
+    let id = editor_id();
+    let mut state = egui::TextEdit::load_state(ctx, id).unwrap_or_default();
+    state.set_ccursor_range(Some(CCursorRange::two(
+        CCursor::new(problems::char_index(source, range.start)),
+        CCursor::new(problems::char_index(source, range.end)),
+    )));
+    state.store(ctx, id);
+    ctx.memory_mut(|memory| memory.request_focus(id));
+}
+
+impl eframe::App for App {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        self.refresh(ctx);
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                ui.with_layout(egui::Layout::top_down(egui::Align::TOP), |ui| {
+                    self.editor(ui);
+                    self.problems(ui);
+
+                    ui.heading(format!("Tokens ({})", self.analysis.tokens.len()));
+                    if let Some(range) = self.tokens.show(ui, &self.analysis.tokens) {
+                        select(ctx, &self.analysis.source, range);
+                    }
+
+                    self.tree(ui);
+
+                    let previous = self.selected;
+                    ui.horizontal(|ui| {
+                        ui.heading("Output");
+                        ui.radio_value(&mut self.selected, Backend::TreeWalker, "Tree-walker");
+                        ui.radio_value(&mut self.selected, Backend::Bytecode, "Bytecode VM");
+                    });
+                    if self.selected != previous {
+                        self.due = Some(Instant::now());
+                        ctx.request_repaint();
+                    }
+                    ui.add_sized(
+                        [ui.available_width(), 220.0],
+                        egui::TextEdit::multiline(
+                            &mut self.analysis.output.as_deref().unwrap_or_default(),
+                        ),
+                    );
+                });
+            });
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn run(source: &str, selected: Backend) -> Analysis {
+        let mut analysis = Analysis::new(source);
+        analysis.run(selected, limits());
+        analysis
+    }
+
+    #[test]
+    fn analysis_runs_valid_programs() {
+        let analysis = Analysis::new("print 1 + 2;");
+        assert_eq!(analysis.tokens.len(), 6);
+        assert_eq!(analysis.statements.len(), 1);
+        assert!(analysis.diagnostics.is_empty());
+        assert_eq!(analysis.output, None);
+
+        let analysis = run("print 1 + 2;", Backend::TreeWalker);
+        assert_eq!(analysis.output.as_deref(), Some("3\n"));
+    }
+
+    #[test]
+    fn analysis_collects_diagnostics() {
+        let analysis = Analysis::new("print 1 @;\nprint 1 +;");
+        assert_eq!(analysis.diagnostics.len(), 2);
+        assert!(analysis
+            .output
+            .is_some_and(|output| output.starts_with("error: unexpected character")));
+
+        let analysis = run("print 1;\nprint -nil;", Backend::Bytecode);
+        assert_eq!(analysis.diagnostics.len(), 1);
+        assert!(analysis
+            .output
+            .is_some_and(|output| output.starts_with("1\nerror: operand must be a number")));
+    }
+
+    #[test]
+    fn runaway_programs_are_stopped() {
+        // Parsing alone never runs the program, so it stays quick.
+        let start = Instant::now();
+        let analysis = Analysis::new("while (true) {}");
+        assert!(analysis.output.is_none());
+        assert!(start.elapsed() < DEBOUNCE);
+
+        let analysis = run("while (true) {}", Backend::Bytecode);
+        assert!(analysis
+            .output
+            .is_some_and(|output| output.contains("exceeded")));
+    }
+
+    #[test]
+    fn replaced_workers_cancel_their_run() {
+        let (_sender, receiver) = mpsc::channel();
+        let cancel = Cancel::new();
+        drop(Worker {
+            receiver,
+            cancel: cancel.clone(),
+        });
+        assert!(cancel.is_cancelled());
+
+        let mut analysis = Analysis::new("while (true) {}");
+        analysis.run(Backend::TreeWalker, limits().with_cancel(cancel));
+        assert!(analysis
+            .output
+            .is_some_and(|output| output.contains("run cancelled")));
+    }
+}
@@ -0,0 +1,142 @@
+use eframe::egui;
+use egui::text::CCursor;
+use egui::text_edit::TextEditOutput;
+use egui::{pos2, Pos2, Stroke};
+use lox_lang::diagnostics::Diagnostic;
+use std::ops::Range;
+
+// Height and period of the wavy underline.
+const AMPLITUDE: f32 = 1.5;
+const WAVELENGTH: f32 = 4.0;
+
+// Underlines every diagnostic in the editor and shows the message of the one
+// under the pointer.
+pub fn show(ui: &egui::Ui, output: &TextEditOutput, source: &str, diagnostics: &[Diagnostic]) {
+    let painter = ui.painter().with_clip_rect(output.text_clip_rect);
+    let stroke = Stroke::new(1.0, ui.visuals().error_fg_color);
+
+    for diagnostic in diagnostics {
+        if let Some(range) = diagnostic.range(source) {
+            for (from, to) in underline(output, source, range) {
+                painter.add(egui::Shape::line(squiggle(from, to), stroke));
+            }
+        }
+    }
+
+    let Some(pointer) = output.response.hover_pos() else {
+        return;
+    };
+    let cursor = output
+        .galley
+        .cursor_from_pos(pointer - output.text_draw_pos);
+    let offset = byte_offset(source, cursor.ccursor.index);
+    let hovered: Vec<&Diagnostic> = diagnostics
+        .iter()
+        .filter(|d| {
+            d.range(source)
+                .is_some_and(|r| r.contains(&offset) || (r.is_empty() && r.start == offset))
+        })
+        .collect();
+    if !hovered.is_empty() {
+        egui::show_tooltip_at_pointer(ui.ctx(), egui::Id::new("diagnostic"), |ui| {
+            for diagnostic in hovered {
+                ui.label(&diagnostic.message);
+            }
+        });
+    }
+}
+
+// A clickable list of the diagnostics. Returns the range of the one that
+// was clicked, for the editor to select.
+pub fn list(ui: &mut egui::Ui, source: &str, diagnostics: &[Diagnostic]) -> Option<Range<usize>> {
+    let mut clicked = None;
+    for diagnostic in diagnostics {
+        let text = egui::RichText::new(diagnostic.to_string()).color(ui.visuals().error_fg_color);
+        if ui.selectable_label(false, text).clicked() {
+            clicked = diagnostic.range(source);
+        }
+    }
+    clicked
+}
+
+// The screen segments under a byte range, one per row of the galley. Empty
+// ranges, such as errors at the end of the file, still get a short mark.
+fn underline(output: &TextEditOutput, source: &str, range: Range<usize>) -> Vec<(Pos2, Pos2)> {
+    let galley = &output.galley;
+    let start = galley.from_ccursor(CCursor::new(char_index(source, range.start)));
+    let end = galley.from_ccursor(CCursor::new(char_index(source, range.end)));
+    let origin = output.text_draw_pos.to_vec2();
+
+    let mut segments = Vec::new();
+    for index in start.rcursor.row..=end.rcursor.row {
+        let Some(row) = galley.rows.get(index) else {
+            break;
+        };
+        let from = if index == start.rcursor.row {
+            row.x_offset(start.rcursor.column)
+        } else {
+            row.rect.min.x
+        };
+        let mut to = if index == end.rcursor.row {
+            row.x_offset(end.rcursor.column)
+        } else {
+            row.rect.max.x
+        };
+        if to - from < WAVELENGTH {
+            to = from + 2.0 * WAVELENGTH;
+        }
+
+        let y = row.rect.max.y;
+        segments.push((pos2(from, y) + origin, pos2(to, y) + origin));
+    }
+    segments
+}
+
+fn squiggle(from: Pos2, to: Pos2) -> Vec<Pos2> {
+    let steps = (((to.x - from.x) / (WAVELENGTH / 2.0)).ceil() as usize).max(1);
+    (0..=steps)
+        .map(|i| {
+            let x = (from.x + i as f32 * WAVELENGTH / 2.0).min(to.x);
+            let y = if i % 2 == 0 { -AMPLITUDE } else { AMPLITUDE };
+            pos2(x, from.y + y)
+        })
+        .collect()
+}
+
+pub fn char_index(source: &str, byte: usize) -> usize {
+    let byte = byte.min(source.len());
+    source.char_indices().take_while(|(i, _)| *i < byte).count()
+}
+
+pub fn byte_offset(source: &str, char_index: usize) -> usize {
+    source
+        .char_indices()
+        .nth(char_index)
+        .map_or(source.len(), |(i, _)| i)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn offsets_round_trip_through_characters() {
+        let source = "print \"é\" + 1;";
+
+        assert_eq!(char_index(source, 7), 7);
+        assert_eq!(char_index(source, 9), 8);
+        assert_eq!(byte_offset(source, 8), 9);
+        assert_eq!(byte_offset(source, 99), source.len());
+        assert_eq!(char_index(source, 99), source.chars().count());
+    }
+
+    #[test]
+    fn squiggles_stay_within_their_segment() {
+        let points = squiggle(pos2(10.0, 20.0), pos2(25.0, 20.0));
+
+        assert_eq!(points.first().map(|p| p.x), Some(10.0));
+        assert_eq!(points.last().map(|p| p.x), Some(25.0));
+        assert!(points.iter().all(|p| (p.y - 20.0).abs() == AMPLITUDE));
+    }
+}
@@ -0,0 +1,260 @@
+use eframe::egui;
+use lox_lang::token::{self, Category};
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::ops::Range;
+
+const CATEGORIES: [(Category, &str); 6] = [
+    (Category::Keyword, "Keywords"),
+    (Category::Literal, "Literals"),
+    (Category::Operator, "Operators"),
+    (Category::Identifier, "Identifiers"),
+    (Category::Punctuation, "Punctuation"),
+    (Category::EndOfFile, "End of file"),
+];
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Field {
+    Type,
+    Lexeme,
+    Literal,
+    Line,
+    Column,
+}
+
+const COLUMNS: [(Field, &str, f32); 5] = [
+    (Field::Type, "Type", 120.0),
+    (Field::Lexeme, "Lexeme", 160.0),
+    (Field::Literal, "Literal", 160.0),
+    (Field::Line, "Line", 60.0),
+    (Field::Column, "Column", 60.0),
+];
+
+// How the token table is sorted and filtered. Kept across analyses so that
+// it survives edits.
+#[derive(Debug)]
+pub struct Table {
+    sort: Field,
+    descending: bool,
+    hidden: HashSet<Category>,
+}
+
+impl Default for Table {
+    fn default() -> Self {
+        Table {
+            sort: Field::Line,
+            descending: false,
+            hidden: HashSet::new(),
+        }
+    }
+}
+
+impl Table {
+    // The tokens left by the filter, in table order. Ties keep source order.
+    pub fn rows<'a>(&self, tokens: &'a [token::Token]) -> Vec<&'a token::Token> {
+        let mut rows: Vec<&token::Token> = tokens
+            .iter()
+            .filter(|t| !self.hidden.contains(&t.typ.category()))
+            .collect();
+        rows.sort_by(|a, b| {
+            let ordering = compare(self.sort, a, b);
+            if self.descending {
+                ordering.reverse()
+            } else {
+                ordering
+            }
+        });
+        rows
+    }
+
+    // Clicking the sorted column again flips its direction.
+    fn sort_by(&mut self, column: Field) {
+        if self.sort == column {
+            self.descending = !self.descending;
+        } else {
+            self.sort = column;
+            self.descending = false;
+        }
+    }
+
+    // Shows the category filter and the table. Returns the source range of
+    // the token whose row was clicked.
+    pub fn show(&mut self, ui: &mut egui::Ui, tokens: &[token::Token]) -> Option<Range<usize>> {
+        ui.horizontal_wrapped(|ui| {
+            for (category, name) in CATEGORIES {
+                let mut shown = !self.hidden.contains(&category);
+                if ui.checkbox(&mut shown, name).changed() {
+                    if shown {
+                        self.hidden.remove(&category);
+                    } else {
+                        self.hidden.insert(category);
+                    }
+                }
+            }
+        });
+
+        ui.horizontal(|ui| {
+            for (column, name, width) in COLUMNS {
+                let text = match (self.sort == column, self.descending) {
+                    (true, false) => format!("{} ⏶", name),
+                    (true, true) => format!("{} ⏷", name),
+                    (false, _) => name.to_owned(),
+                };
+                let header = egui::SelectableLabel::new(self.sort == column, text);
+                if ui.add_sized([width, 0.0], header).clicked() {
+                    self.sort_by(column);
+                }
+            }
+        });
+        ui.separator();
+
+        // Only the visible rows are laid out, which keeps long files cheap.
+        let rows = self.rows(tokens);
+        let height = ui.text_style_height(&egui::TextStyle::Body);
+        let mut clicked = None;
+        egui::ScrollArea::vertical()
+            .id_source("tokens")
+            .max_height(220.0)
+            .auto_shrink([false, true])
+            .show_rows(ui, height, rows.len(), |ui, visible| {
+                for token in &rows[visible] {
+                    let row = ui.horizontal(|ui| {
+                        let mut clicked = false;
+                        for (column, _, width) in COLUMNS {
+                            let cell = egui::SelectableLabel::new(false, cell(token, column));
+                            clicked |= ui.add_sized([width, height], cell).clicked();
+                        }
+                        clicked
+                    });
+                    if row.inner {
+                        clicked = Some(token.span.start..token.span.end);
+                    }
+                }
+            });
+        clicked
+    }
+}
+
+fn cell(token: &token::Token, column: Field) -> String {
+    match column {
+        Field::Type => format!("{:?}", token.typ),
+        Field::Lexeme => token.lexeme.clone(),
+        Field::Literal => literal(token).map_or_else(String::new, describe),
+        Field::Line => token.span.line.to_string(),
+        Field::Column => token.span.column.to_string(),
+    }
+}
+
+// The value a token stands for. The scanner also keeps the lexeme of names
+// and punctuation as a string, which is not shown.
+fn literal(token: &token::Token) -> Option<&token::Value> {
+    match token.typ {
+        token::Type::String
+        | token::Type::Number
+        | token::Type::True
+        | token::Type::False
+        | token::Type::Nil => Some(&token.literal),
+        _ => None,
+    }
+}
+
+fn describe(value: &token::Value) -> String {
+    match value {
+        token::Value::Str(s) => format!("{:?}", s),
+        token::Value::Bool(b) => b.to_string(),
+        token::Value::Num(n) => n.to_string(),
+        token::Value::None => "nil".to_owned(),
+    }
+}
+
+fn compare(column: Field, a: &token::Token, b: &token::Token) -> Ordering {
+    let position = |t: &token::Token| (t.span.line, t.span.column);
+    match column {
+        Field::Line => position(a).cmp(&position(b)),
+        Field::Column => a.span.column.cmp(&b.span.column),
+        Field::Literal => match (literal(a), literal(b)) {
+            (Some(token::Value::Num(x)), Some(token::Value::Num(y))) => x.total_cmp(y),
+            (Some(token::Value::Str(x)), Some(token::Value::Str(y))) => x.cmp(y),
+            (Some(token::Value::Bool(x)), Some(token::Value::Bool(y))) => x.cmp(y),
+            (x, y) => rank(x).cmp(&rank(y)),
+        },
+        _ => cell(a, column).cmp(&cell(b, column)),
+    }
+}
+
+// Orders literals of different kinds: tokens without one first, then nil,
+// booleans, numbers and strings.
+fn rank(value: Option<&token::Value>) -> u8 {
+    match value {
+        None => 0,
+        Some(token::Value::None) => 1,
+        Some(token::Value::Bool(_)) => 2,
+        Some(token::Value::Num(_)) => 3,
+        Some(token::Value::Str(_)) => 4,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use lox_lang::scanner;
+
+    fn lexemes(rows: &[&token::Token]) -> Vec<String> {
+        rows.iter().map(|t| t.lexeme.clone()).collect()
+    }
+
+    #[test]
+    fn filters_by_category() {
+        let tokens = scanner::Scanner::new("var a = 10 + 2 or true and nil;").parse();
+        let mut table = Table::default();
+        assert_eq!(table.rows(&tokens).len(), tokens.len());
+
+        table.hidden = HashSet::from([
+            Category::Identifier,
+            Category::Punctuation,
+            Category::EndOfFile,
+        ]);
+        assert_eq!(
+            lexemes(&table.rows(&tokens)),
+            vec!["var", "=", "10", "+", "2", "or", "true", "and", "nil"]
+        );
+
+        table.hidden.extend([Category::Keyword, Category::Operator]);
+        assert_eq!(
+            lexemes(&table.rows(&tokens)),
+            vec!["10", "2", "true", "nil"]
+        );
+        assert_eq!(cell(&tokens[3], Field::Literal), "10");
+        assert_eq!(cell(&tokens[1], Field::Literal), "");
+    }
+
+    #[test]
+    fn sorts_by_column() {
+        let tokens = scanner::Scanner::new("print 10;\nprint 9;").parse();
+        let mut table = Table {
+            hidden: HashSet::from([Category::EndOfFile]),
+            ..Table::default()
+        };
+
+        table.sort_by(Field::Literal);
+        assert_eq!(
+            lexemes(&table.rows(&tokens)),
+            vec!["print", ";", "print", ";", "9", "10"]
+        );
+
+        table.sort_by(Field::Line);
+        table.sort_by(Field::Line);
+        assert!(table.descending);
+        assert_eq!(
+            lexemes(&table.rows(&tokens)),
+            vec![";", "9", "print", ";", "10", "print"]
+        );
+
+        table.sort_by(Field::Type);
+        assert_eq!(
+            lexemes(&table.rows(&tokens)),
+            vec!["10", "9", "print", "print", ";", ";"]
+        );
+    }
+}
@@ -0,0 +1,295 @@
+use eframe::egui;
+use egui::collapsing_header::CollapsingState;
+use lox_lang::{ast, token};
+use std::ops::Range;
+
+// A node of the syntax tree as the tree view shows it. Functions get their
+// own kind because class methods are not statements.
+#[derive(Clone, Copy)]
+pub enum Node<'a> {
+    Stmt(&'a ast::Stmt),
+    Expr(&'a ast::Expr),
+    Function(&'a ast::Function),
+}
+
+impl<'a> Node<'a> {
+    pub fn label(&self) -> String {
+        match *self {
+            Node::Stmt(stmt) => match stmt {
+                ast::Stmt::Block(_) => "Block".to_owned(),
+                ast::Stmt::Class(c) => match &c.superclass {
+                    Some(superclass) => {
+                        format!("Class {} < {}", c.name.lexeme, superclass.name.lexeme)
+                    }
+                    None => format!("Class {}", c.name.lexeme),
+                },
+                ast::Stmt::Expression(_) => "Expression".to_owned(),
+                ast::Stmt::Function(f) => Node::Function(f).label(),
+                ast::Stmt::If(_) => "If".to_owned(),
+                ast::Stmt::Print(_) => "Print".to_owned(),
+                ast::Stmt::Return(_) => "Return".to_owned(),
+                ast::Stmt::Var(v) => format!("Var {}", v.name.lexeme),
+                ast::Stmt::While(_) => "While".to_owned(),
+            },
+            Node::Expr(exp) => match exp {
+                ast::Expr::Assign(a) => format!("Assign {}", a.name.lexeme),
+                ast::Expr::Binary(b) => format!("Binary {}", b.op.lexeme),
+                ast::Expr::Call(_) => "Call".to_owned(),
+                ast::Expr::Get(g) => format!("Get .{}", g.name.lexeme),
+                ast::Expr::Grouping(_) => "Grouping".to_owned(),
+                ast::Expr::Literal(l) => format!("Literal {}", literal(&l.value)),
+                ast::Expr::Logical(l) => format!("Logical {}", l.op.lexeme),
+                ast::Expr::Set(s) => format!("Set .{}", s.name.lexeme),
+                ast::Expr::Super(s) => format!("Super .{}", s.method.lexeme),
+                ast::Expr::This(_) => "This".to_owned(),
+                ast::Expr::Unary(u) => format!("Unary {}", u.op.lexeme),
+                ast::Expr::Variable(v) => format!("Variable {}", v.name.lexeme),
+                ast::Expr::None => "Error".to_owned(),
+            },
+            Node::Function(f) => {
+                let params: Vec<&str> = f.params.iter().map(|p| p.lexeme.as_str()).collect();
+                format!("Function {}({})", f.name.lexeme, params.join(", "))
+            }
+        }
+    }
+
+    pub fn span(&self) -> Option<token::Span> {
+        match *self {
+            Node::Stmt(stmt) => Some(stmt.span()),
+            Node::Expr(exp) => exp.span(),
+            Node::Function(f) => Some(f.span),
+        }
+    }
+
+    pub fn children(&self) -> Vec<Node<'a>> {
+        let mut children = Vec::new();
+        match *self {
+            Node::Stmt(stmt) => match stmt {
+                ast::Stmt::Block(b) => children.extend(b.statements.iter().map(Node::Stmt)),
+                ast::Stmt::Class(c) => {
+                    children.extend(c.methods.iter().map(|m| Node::Function(m)));
+                }
+                ast::Stmt::Expression(e) => children.push(Node::Expr(&e.exp)),
+                ast::Stmt::Function(f) => return Node::Function(f).children(),
+                ast::Stmt::If(i) => {
+                    children.push(Node::Expr(&i.condition));
+                    children.push(Node::Stmt(&i.then_branch));
+                    children.extend(i.else_branch.iter().map(Node::Stmt));
+                }
+                ast::Stmt::Print(p) => children.push(Node::Expr(&p.exp)),
+                ast::Stmt::Return(r) => children.extend(r.value.iter().map(Node::Expr)),
+                ast::Stmt::Var(v) => children.extend(v.initializer.iter().map(Node::Expr)),
+                ast::Stmt::While(w) => {
+                    children.push(Node::Expr(&w.condition));
+                    children.push(Node::Stmt(&w.body));
+                }
+            },
+            Node::Expr(exp) => match exp {
+                ast::Expr::Assign(a) => children.push(Node::Expr(&a.value)),
+                ast::Expr::Binary(b) => {
+                    children.push(Node::Expr(&b.left));
+                    children.push(Node::Expr(&b.right));
+                }
+                ast::Expr::Call(c) => {
+                    children.push(Node::Expr(&c.callee));
+                    children.extend(c.arguments.iter().map(Node::Expr));
+                }
+                ast::Expr::Get(g) => children.push(Node::Expr(&g.object)),
+                ast::Expr::Grouping(g) => children.push(Node::Expr(&g.exp)),
+                ast::Expr::Logical(l) => {
+                    children.push(Node::Expr(&l.left));
+                    children.push(Node::Expr(&l.right));
+                }
+                ast::Expr::Set(s) => {
+                    children.push(Node::Expr(&s.object));
+                    children.push(Node::Expr(&s.value));
+                }
+                ast::Expr::Unary(u) => children.push(Node::Expr(&u.right)),
+                ast::Expr::Literal(_)
+                | ast::Expr::Super(_)
+                | ast::Expr::This(_)
+                | ast::Expr::Variable(_)
+                | ast::Expr::None => {}
+            },
+            Node::Function(f) => children.extend(f.body.iter().map(Node::Stmt)),
+        }
+        children
+    }
+}
+
+fn literal(value: &token::Value) -> String {
+    match value {
+        token::Value::Str(s) => format!("{:?}", s),
+        token::Value::Bool(b) => b.to_string(),
+        token::Value::Num(n) => n.to_string(),
+        token::Value::None => "nil".to_owned(),
+    }
+}
+
+// The child indices leading to the innermost node that covers `range`, or
+// an empty path if no statement does.
+pub fn innermost(statements: &[ast::Stmt], range: Range<usize>) -> Vec<usize> {
+    let covers = |node: &Node| {
+        node.span()
+            .is_some_and(|span| span.start <= range.start && range.end <= span.end)
+    };
+
+    let mut path = Vec::new();
+    let mut nodes: Vec<Node> = statements.iter().map(Node::Stmt).collect();
+    while let Some(index) = nodes.iter().position(covers) {
+        path.push(index);
+        nodes = nodes[index].children();
+    }
+    path
+}
+
+// What the tree view should point out this frame.
+pub struct Focus<'p> {
+    // The node to highlight, as a path of child indices.
+    pub selected: &'p [usize],
+    // Whether to open the selected node's ancestors and scroll to it, which
+    // is only done when the selection changes so that it can still be
+    // collapsed by hand.
+    pub reveal: bool,
+}
+
+// Shows the statements as a collapsible tree. Returns the path of the node
+// that was clicked, if any.
+pub fn show(ui: &mut egui::Ui, statements: &[ast::Stmt], focus: &Focus) -> Option<Vec<usize>> {
+    let mut clicked = None;
+    let mut path = Vec::new();
+    for (index, stmt) in statements.iter().enumerate() {
+        path.push(index);
+        node(ui, Node::Stmt(stmt), &mut path, focus, &mut clicked);
+        path.pop();
+    }
+    clicked
+}
+
+fn node(
+    ui: &mut egui::Ui,
+    node: Node,
+    path: &mut Vec<usize>,
+    focus: &Focus,
+    clicked: &mut Option<Vec<usize>>,
+) {
+    let selected = focus.selected == path.as_slice();
+    let mut label = |ui: &mut egui::Ui| {
+        let response = ui.selectable_label(selected, node.label());
+        if response.clicked() {
+            *clicked = Some(path.clone());
+        }
+        if selected && focus.reveal {
+            response.scroll_to_me(Some(egui::Align::Center));
+        }
+    };
+
+    let children = node.children();
+    if children.is_empty() {
+        ui.horizontal(|ui| {
+            ui.add_space(ui.spacing().indent);
+            label(ui);
+        });
+        return;
+    }
+
+    let id = ui.make_persistent_id(("ast", path.as_slice()));
+    let mut state = CollapsingState::load_with_default_open(ui.ctx(), id, false);
+    if focus.reveal && focus.selected.len() > path.len() && focus.selected.starts_with(path) {
+        state.set_open(true);
+    }
+    state.show_header(ui, label).body(|ui| {
+        for (index, child) in children.into_iter().enumerate() {
+            path.push(index);
+            self::node(ui, child, path, focus, clicked);
+            path.pop();
+        }
+    });
+}
+
+// The node at the end of a path of child indices.
+pub fn find<'a>(statements: &'a [ast::Stmt], path: &[usize]) -> Option<Node<'a>> {
+    let (first, rest) = path.split_first()?;
+    let mut node = Node::Stmt(statements.get(*first)?);
+    for index in rest {
+        node = node.children().into_iter().nth(*index)?;
+    }
+    Some(node)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use lox_lang::{parser, scanner};
+
+    fn parse(source: &str) -> Vec<ast::Stmt> {
+        let tokens = scanner::Scanner::new(source).parse();
+        parser::Parser::new(tokens).parse().0
+    }
+
+    fn labels(statements: &[ast::Stmt], path: &[usize]) -> Vec<String> {
+        (1..=path.len())
+            .map(|depth| find(statements, &path[..depth]).unwrap().label())
+            .collect()
+    }
+
+    #[test]
+    fn innermost_node_under_the_cursor() {
+        let source = "var a = 1;\nprint (a + 2) * -a;";
+        let statements = parse(source);
+
+        let offset = source.find("2)").unwrap();
+        let path = innermost(&statements, offset..offset);
+        assert_eq!(path, vec![1, 0, 0, 0, 1]);
+        assert_eq!(
+            labels(&statements, &path),
+            vec!["Print", "Binary *", "Grouping", "Binary +", "Literal 2"]
+        );
+
+        let offset = source.find("-a").unwrap();
+        let path = innermost(&statements, offset..offset + 2);
+        assert_eq!(labels(&statements, &path).last().unwrap(), "Unary -");
+
+        assert_eq!(
+            innermost(&statements, source.len() + 1..source.len() + 1),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn functions_and_classes() {
+        let source = "class B < A { init(x) { this.x = x; } }";
+        let statements = parse(source);
+
+        let offset = source.find("= x").unwrap() + 2;
+        let path = innermost(&statements, offset..offset);
+        assert_eq!(
+            labels(&statements, &path),
+            vec![
+                "Class B < A",
+                "Function init(x)",
+                "Expression",
+                "Set .x",
+                "Variable x"
+            ]
+        );
+    }
+
+    #[test]
+    fn every_node_has_a_span_within_its_parent() {
+        let source = "fun f(n) { for (var i = 0; i < n; i = i + 1) { if (i > 1 or nil) return \"s\"; } }\nprint f(3) and !true;";
+        let statements = parse(source);
+
+        fn check(node: Node, parent: token::Span) {
+            let span = node.span().unwrap();
+            assert!(parent.start <= span.start && span.end <= parent.end);
+            for child in node.children() {
+                check(child, span);
+            }
+        }
+        for stmt in &statements {
+            check(Node::Stmt(stmt), stmt.span());
+        }
+    }
+}
@@ -0,0 +1,349 @@
+use super::chunk::FunctionProto;
+use std::collections::HashMap;
+use std::mem;
+use std::rc::Rc;
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct ObjRef(usize);
+
+#[derive(Debug, Clone, Copy)]
+pub enum Value {
+    Nil,
+    Bool(bool),
+    Num(f64),
+    Obj(ObjRef),
+}
+
+impl Value {
+    pub fn is_falsey(&self) -> bool {
+        matches!(self, Value::Nil | Value::Bool(false))
+    }
+}
+
+pub struct Native {
+    pub name: &'static str,
+    pub arity: usize,
+    pub function: fn(&[Value]) -> Value,
+}
+
+pub enum Upvalue {
+    Open(usize),
+    Closed(Value),
+}
+
+pub struct Closure {
+    pub function: Rc<FunctionProto>,
+    pub upvalues: Vec<ObjRef>,
+}
+
+pub struct Class {
+    pub name: Rc<str>,
+    pub methods: HashMap<Rc<str>, ObjRef>,
+}
+
+pub struct Instance {
+    pub class: ObjRef,
+    pub fields: HashMap<Rc<str>, Value>,
+}
+
+pub struct BoundMethod {
+    pub receiver: Value,
+    pub method: ObjRef,
+}
+
+pub enum Obj {
+    String(Rc<str>),
+    Native(Native),
+    Closure(Closure),
+    Upvalue(Upvalue),
+    Class(Class),
+    Instance(Instance),
+    BoundMethod(BoundMethod),
+}
+
+impl Obj {
+    // Approximate bytes the object occupies, counted when it is allocated and
+    // again whenever it grows.
+    fn size(&self) -> usize {
+        let payload = match self {
+            Obj::String(s) => s.len(),
+            Obj::Closure(closure) => closure.upvalues.len() * mem::size_of::<ObjRef>(),
+            Obj::Class(class) => class.methods.len() * mem::size_of::<(Rc<str>, ObjRef)>(),
+            Obj::Instance(instance) => instance.fields.len() * mem::size_of::<(Rc<str>, Value)>(),
+            Obj::Native(_) | Obj::Upvalue(_) | Obj::BoundMethod(_) => 0,
+        };
+        mem::size_of::<Obj>() + payload
+    }
+}
+
+// Collections start once this many objects are live and the threshold grows
+// with the survivors so that collection cost stays proportional to allocation.
+const INITIAL_THRESHOLD: usize = 1024;
+const GROWTH_FACTOR: usize = 2;
+
+pub struct Heap {
+    objects: Vec<Option<Obj>>,
+    marks: Vec<bool>,
+    free: Vec<usize>,
+    gray: Vec<ObjRef>,
+    live: usize,
+    sizes: Vec<usize>,
+    bytes: usize,
+    threshold: usize,
+    stress: bool,
+}
+
+impl Default for Heap {
+    fn default() -> Self {
+        Heap {
+            objects: Vec::new(),
+            marks: Vec::new(),
+            free: Vec::new(),
+            gray: Vec::new(),
+            live: 0,
+            sizes: Vec::new(),
+            bytes: 0,
+            threshold: INITIAL_THRESHOLD,
+            stress: false,
+        }
+    }
+}
+
+impl Heap {
+    pub fn new() -> Self {
+        Heap::default()
+    }
+
+    pub fn alloc(&mut self, obj: Obj) -> ObjRef {
+        let size = obj.size();
+        self.live += 1;
+        self.bytes += size;
+        match self.free.pop() {
+            Some(index) => {
+                self.objects[index] = Some(obj);
+                self.sizes[index] = size;
+                ObjRef(index)
+            }
+            None => {
+                self.objects.push(Some(obj));
+                self.marks.push(false);
+                self.sizes.push(size);
+                ObjRef(self.objects.len() - 1)
+            }
+        }
+    }
+
+    pub fn get(&self, r: ObjRef) -> &Obj {
+        self.objects[r.0]
+            .as_ref()
+            .expect("use of a collected object")
+    }
+
+    pub fn get_mut(&mut self, r: ObjRef) -> &mut Obj {
+        self.objects[r.0]
+            .as_mut()
+            .expect("use of a collected object")
+    }
+
+    // Recounts an object whose fields or methods changed.
+    pub fn resize(&mut self, r: ObjRef) {
+        let size = self.get(r).size();
+        self.bytes = self.bytes + size - self.sizes[r.0];
+        self.sizes[r.0] = size;
+    }
+
+    pub fn len(&self) -> usize {
+        self.live
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.live == 0
+    }
+
+    // Approximate bytes held by live objects.
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    // Garbage collection
+
+    pub fn set_stress(&mut self, stress: bool) {
+        self.stress = stress;
+    }
+
+    pub fn should_collect(&self) -> bool {
+        self.stress || self.live >= self.threshold
+    }
+
+    pub fn mark_value(&mut self, value: Value) {
+        if let Value::Obj(r) = value {
+            self.mark_object(r);
+        }
+    }
+
+    pub fn mark_object(&mut self, r: ObjRef) {
+        if !self.marks[r.0] {
+            self.marks[r.0] = true;
+            self.gray.push(r);
+        }
+    }
+
+    // Traces everything reachable from the marked roots, frees the rest and
+    // returns how many objects were collected.
+    pub fn collect(&mut self) -> usize {
+        while let Some(r) = self.gray.pop() {
+            self.blacken(r);
+        }
+
+        let before = self.live;
+        for index in 0..self.objects.len() {
+            if self.marks[index] {
+                self.marks[index] = false;
+            } else if self.objects[index].take().is_some() {
+                self.free.push(index);
+                self.live -= 1;
+                self.bytes -= self.sizes[index];
+            }
+        }
+
+        self.threshold = (self.live * GROWTH_FACTOR).max(INITIAL_THRESHOLD);
+        before - self.live
+    }
+
+    fn blacken(&mut self, r: ObjRef) {
+        let mut children = Vec::new();
+        match self.get(r) {
+            Obj::String(_) | Obj::Native(_) | Obj::Upvalue(Upvalue::Open(_)) => (),
+            Obj::Upvalue(Upvalue::Closed(value)) => children.push(*value),
+            Obj::Closure(closure) => {
+                children.extend(closure.upvalues.iter().map(|u| Value::Obj(*u)));
+            }
+            Obj::Class(class) => {
+                children.extend(class.methods.values().map(|m| Value::Obj(*m)));
+            }
+            Obj::Instance(instance) => {
+                children.push(Value::Obj(instance.class));
+                children.extend(instance.fields.values().copied());
+            }
+            Obj::BoundMethod(bound) => {
+                children.push(bound.receiver);
+                children.push(Value::Obj(bound.method));
+            }
+        }
+
+        for child in children {
+            self.mark_value(child);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn string(heap: &mut Heap, s: &str) -> ObjRef {
+        heap.alloc(Obj::String(Rc::from(s)))
+    }
+
+    #[test]
+    fn unreachable_objects_are_freed() {
+        let mut heap = Heap::new();
+        let kept = string(&mut heap, "kept");
+        string(&mut heap, "garbage");
+
+        heap.mark_object(kept);
+        assert_eq!(heap.collect(), 1);
+        assert_eq!(heap.len(), 1);
+        assert_eq!(
+            match heap.get(kept) {
+                Obj::String(s) => s.to_string(),
+                _ => "".to_owned(),
+            },
+            "kept"
+        );
+    }
+
+    #[test]
+    fn references_are_traced() {
+        let mut heap = Heap::new();
+        let class = heap.alloc(Obj::Class(Class {
+            name: Rc::from("A"),
+            methods: HashMap::new(),
+        }));
+        let field = string(&mut heap, "field");
+        let closed = heap.alloc(Obj::Upvalue(Upvalue::Closed(Value::Obj(field))));
+        let mut fields = HashMap::new();
+        fields.insert(Rc::from("up"), Value::Obj(closed));
+        let instance = heap.alloc(Obj::Instance(Instance { class, fields }));
+        string(&mut heap, "garbage");
+
+        heap.mark_value(Value::Obj(instance));
+        assert_eq!(heap.collect(), 1);
+        assert_eq!(heap.len(), 4);
+    }
+
+    #[test]
+    fn freed_slots_are_reused() {
+        let mut heap = Heap::new();
+        let first = string(&mut heap, "a");
+        heap.collect();
+
+        assert_eq!(string(&mut heap, "b"), first);
+        assert_eq!(heap.len(), 1);
+    }
+
+    #[test]
+    fn bytes_follow_live_objects() {
+        let mut heap = Heap::new();
+        let kept = string(&mut heap, "kept");
+        string(&mut heap, "a longer piece of garbage");
+        assert_eq!(heap.bytes(), 2 * mem::size_of::<Obj>() + 4 + 25);
+
+        heap.mark_object(kept);
+        heap.collect();
+        assert_eq!(heap.bytes(), mem::size_of::<Obj>() + 4);
+    }
+
+    #[test]
+    fn resized_objects_count_their_growth() {
+        let mut heap = Heap::new();
+        let class = heap.alloc(Obj::Class(Class {
+            name: Rc::from("A"),
+            methods: HashMap::new(),
+        }));
+        let instance = heap.alloc(Obj::Instance(Instance {
+            class,
+            fields: HashMap::new(),
+        }));
+        let before = heap.bytes();
+
+        if let Obj::Instance(object) = heap.get_mut(instance) {
+            object.fields.insert(Rc::from("x"), Value::Nil);
+        }
+        heap.resize(instance);
+        assert_eq!(heap.bytes(), before + mem::size_of::<(Rc<str>, Value)>());
+
+        heap.mark_object(class);
+        heap.collect();
+        assert_eq!(heap.bytes(), mem::size_of::<Obj>());
+    }
+
+    #[test]
+    fn threshold_grows_with_survivors() {
+        let mut heap = Heap::new();
+        let refs: Vec<ObjRef> = (0..INITIAL_THRESHOLD)
+            .map(|i| string(&mut heap, &i.to_string()))
+            .collect();
+        assert!(heap.should_collect());
+
+        for r in &refs {
+            heap.mark_object(*r);
+        }
+        heap.collect();
+        assert!(!heap.should_collect());
+
+        heap.set_stress(true);
+        assert!(heap.should_collect());
+    }
+}
@@ -0,0 +1,391 @@
+use super::callable::Native;
+use super::interpreter::{RuntimeError, Value};
+use super::native::{NativeError, NativeFunction};
+use super::token;
+use std::any::Any;
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+// A Rust value that scripts use like an instance of a Lox class. Properties
+// are read before methods, the same order Lox instances use for fields.
+//
+// While a method runs, its receiver is mutably borrowed, so a method that
+// takes other host objects must read them with `try_borrow`: it fails when
+// the argument is the receiver itself, as in `p.dist(p)`. Such an object
+// only equals itself and prints as `host instance`.
+pub trait HostObject: Any {
+    fn class_name(&self) -> &str;
+
+    fn get_property(&self, _name: &str) -> Option<Value> {
+        None
+    }
+
+    fn set_property(&mut self, name: &str, _value: Value) -> Result<(), NativeError> {
+        Err(NativeError::Message(format!(
+            "can't set property '{}' on {}",
+            name,
+            self.class_name()
+        )))
+    }
+
+    // The arity of the method called `name`, or `None` if there is none.
+    fn method_arity(&self, _name: &str) -> Option<usize> {
+        None
+    }
+
+    fn call_method(&mut self, name: &str, _arguments: &[Value]) -> Result<Value, NativeError> {
+        Err(NativeError::Message(format!(
+            "undefined property '{}'",
+            name
+        )))
+    }
+
+    // What `print` shows for the object.
+    fn display(&self) -> String {
+        format!("{} instance", self.class_name())
+    }
+
+    // Equality beyond identity, which always holds. Downcast `other` through
+    // `Any` to compare against objects of the same type.
+    fn equals(&self, _other: &dyn HostObject) -> bool {
+        false
+    }
+}
+
+impl fmt::Debug for dyn HostObject {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.display())
+    }
+}
+
+impl Value {
+    pub fn host<T: HostObject>(object: T) -> Value {
+        Value::Host(Rc::new(RefCell::new(object)))
+    }
+}
+
+// A host type that scripts construct by calling it, as in `Point(1, 2)`.
+// The constructor usually builds its object with `Value::host`. Lox classes
+// can't inherit from it.
+pub struct HostClass {
+    pub name: String,
+    constructor: Rc<dyn NativeFunction>,
+}
+
+impl HostClass {
+    pub fn new(name: &str, constructor: Rc<dyn NativeFunction>) -> Self {
+        HostClass {
+            name: name.to_owned(),
+            constructor,
+        }
+    }
+}
+
+impl NativeFunction for HostClass {
+    fn arity(&self) -> usize {
+        self.constructor.arity()
+    }
+
+    fn call(&self, arguments: &[Value]) -> Result<Value, NativeError> {
+        self.constructor.call(arguments)
+    }
+}
+
+impl fmt::Display for HostClass {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+impl fmt::Debug for HostClass {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<class {}>", self.name)
+    }
+}
+
+pub fn get(
+    object: &Rc<RefCell<dyn HostObject>>,
+    name: &token::Token,
+) -> Result<Value, RuntimeError> {
+    let host = object.try_borrow().map_err(|_| in_use(name))?;
+    if let Some(value) = host.get_property(name.name()) {
+        return Ok(value);
+    }
+
+    match host.method_arity(name.name()) {
+        Some(arity) => {
+            let method = HostMethod {
+                object: object.clone(),
+                name: name.name().to_owned(),
+                arity,
+            };
+            Ok(Value::Native(Rc::new(Native::new(
+                name.name(),
+                Rc::new(method),
+            ))))
+        }
+        None => Err(RuntimeError::new(
+            name,
+            &format!("undefined property '{}'", name.name()),
+        )),
+    }
+}
+
+pub fn set(
+    object: &Rc<RefCell<dyn HostObject>>,
+    name: &token::Token,
+    value: Value,
+) -> Result<(), RuntimeError> {
+    object
+        .try_borrow_mut()
+        .map_err(|_| in_use(name))?
+        .set_property(name.name(), value)
+        .map_err(|err| RuntimeError::new(name, &err.describe(name.name())))
+}
+
+fn in_use(name: &token::Token) -> RuntimeError {
+    RuntimeError::new(
+        name,
+        &format!("can't access '{}' while the object is in use", name.name()),
+    )
+}
+
+// A method looked up on a host object, bound to that object.
+struct HostMethod {
+    object: Rc<RefCell<dyn HostObject>>,
+    name: String,
+    arity: usize,
+}
+
+impl NativeFunction for HostMethod {
+    fn arity(&self) -> usize {
+        self.arity
+    }
+
+    fn call(&self, arguments: &[Value]) -> Result<Value, NativeError> {
+        let mut object = self.object.try_borrow_mut().map_err(|_| {
+            NativeError::Message(format!(
+                "can't call '{}' while the object is in use",
+                self.name
+            ))
+        })?;
+        object.call_method(&self.name, arguments)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::super::interpreter::OutputBuffer;
+    use super::super::lox::Lox;
+    use super::super::scanner;
+    use super::*;
+
+    #[derive(PartialEq)]
+    struct Point {
+        x: f64,
+        y: f64,
+    }
+
+    impl HostObject for Point {
+        fn class_name(&self) -> &str {
+            "Point"
+        }
+
+        fn get_property(&self, name: &str) -> Option<Value> {
+            match name {
+                "x" => Some(Value::Num(self.x)),
+                "y" => Some(Value::Num(self.y)),
+                _ => None,
+            }
+        }
+
+        fn set_property(&mut self, name: &str, value: Value) -> Result<(), NativeError> {
+            let n = match value {
+                Value::Num(n) => n,
+                _ => return Err(format!("'{}' must be a number", name).into()),
+            };
+            match name {
+                "x" => self.x = n,
+                "y" => self.y = n,
+                _ => return Err(format!("Point has no property '{}'", name).into()),
+            }
+            Ok(())
+        }
+
+        fn method_arity(&self, name: &str) -> Option<usize> {
+            match name {
+                "length" => Some(0),
+                "scale" => Some(1),
+                "dist" => Some(1),
+                "show" => Some(1),
+                "matches" => Some(1),
+                _ => None,
+            }
+        }
+
+        fn call_method(&mut self, name: &str, arguments: &[Value]) -> Result<Value, NativeError> {
+            match (name, arguments) {
+                ("length", []) => Ok(Value::Num(self.x.hypot(self.y))),
+                ("dist", [Value::Host(other)]) => {
+                    // Failing to borrow means `other` is this point.
+                    let Ok(other) = other.try_borrow() else {
+                        return Ok(Value::Num(0.0));
+                    };
+                    match (&*other as &dyn Any).downcast_ref::<Point>() {
+                        Some(p) => Ok(Value::Num((self.x - p.x).hypot(self.y - p.y))),
+                        None => Err("expected a Point".into()),
+                    }
+                }
+                ("show", [value]) => Ok(Value::Str(value.to_string())),
+                ("matches", [value]) => {
+                    let copy = Value::host(Point {
+                        x: self.x,
+                        y: self.y,
+                    });
+                    Ok(Value::Bool(*value == copy))
+                }
+                ("scale", [Value::Num(factor)]) => {
+                    self.x *= factor;
+                    self.y *= factor;
+                    Ok(Value::None)
+                }
+                _ => Err("bad arguments".into()),
+            }
+        }
+
+        fn display(&self) -> String {
+            format!("Point({}, {})", self.x, self.y)
+        }
+
+        fn equals(&self, other: &dyn HostObject) -> bool {
+            (other as &dyn Any).downcast_ref::<Point>() == Some(self)
+        }
+    }
+
+    fn lox(output: &OutputBuffer) -> Lox {
+        let mut lox = Lox::with_output(Box::new(output.clone()));
+        lox.register_class("Point", |x: f64, y: f64| Value::host(Point { x, y }));
+        lox
+    }
+
+    #[test]
+    fn properties_and_methods() {
+        let output = OutputBuffer::new();
+        let mut lox = lox(&output);
+
+        lox.eval(
+            "var p = Point(3, 4);
+             print p.length();
+             p.x = 6; p.y = 8;
+             var scale = p.scale;
+             scale(0.5);
+             print p;",
+        )
+        .unwrap();
+        assert_eq!(output.contents(), "5\nPoint(3, 4)\n");
+    }
+
+    #[test]
+    fn classes() {
+        let output = OutputBuffer::new();
+        let mut lox = lox(&output);
+
+        lox.eval("print Point; print Point(1, 2);").unwrap();
+        assert_eq!(output.contents(), "Point\nPoint(1, 2)\n");
+        assert_eq!(
+            lox.call("Point", vec![Value::Num(3.0), Value::Num(4.0)])
+                .unwrap()
+                .to_string(),
+            "Point(3, 4)"
+        );
+        assert_eq!(
+            lox.eval("Point(1);").unwrap_err().to_string(),
+            "line 1: expected 2 arguments but got 1"
+        );
+        assert_eq!(
+            lox.eval("class P < Point {}").unwrap_err().to_string(),
+            "line 1: can't inherit from host class 'Point'"
+        );
+    }
+
+    #[test]
+    fn equality() {
+        let output = OutputBuffer::new();
+        let mut lox = lox(&output);
+
+        assert_eq!(
+            lox.eval("var p = Point(1, 2); p == p;").unwrap(),
+            Value::Bool(true)
+        );
+        assert_eq!(
+            lox.eval("Point(1, 2) == Point(1, 2);").unwrap(),
+            Value::Bool(true)
+        );
+        assert_eq!(
+            lox.eval("Point(1, 2) == Point(2, 1);").unwrap(),
+            Value::Bool(false)
+        );
+        assert_eq!(lox.eval("Point(1, 2) == 1;").unwrap(), Value::Bool(false));
+    }
+
+    #[test]
+    fn errors() {
+        let output = OutputBuffer::new();
+        let mut lox = lox(&output);
+
+        assert_eq!(
+            lox.eval("Point(1, 2).z;").unwrap_err().to_string(),
+            "line 1: undefined property 'z'"
+        );
+        assert_eq!(
+            lox.eval("Point(1, 2).x = \"a\";").unwrap_err().to_string(),
+            "line 1: 'x' must be a number"
+        );
+        assert_eq!(
+            lox.eval("Point(1, 2).length(1);").unwrap_err().to_string(),
+            "line 1: expected 0 arguments but got 1"
+        );
+    }
+
+    #[test]
+    fn receivers_passed_as_arguments() {
+        let output = OutputBuffer::new();
+        let mut lox = lox(&output);
+
+        lox.eval("var p = Point(3, 4); print p.dist(Point(0, 0)); print p.dist(p);")
+            .unwrap();
+        assert_eq!(output.contents(), "5\n0\n");
+    }
+
+    #[test]
+    fn receivers_in_use_compare_and_print() {
+        let output = OutputBuffer::new();
+        let mut lox = lox(&output);
+
+        lox.eval(
+            "var p = Point(3, 4);
+             print p.show(p); print p.show(Point(1, 2));
+             print p.matches(p); print p.matches(Point(3, 4));",
+        )
+        .unwrap();
+        assert_eq!(
+            output.contents(),
+            "host instance\nPoint(1, 2)\nfalse\ntrue\n"
+        );
+    }
+
+    #[test]
+    fn borrowed_objects_raise_errors() {
+        let object: Rc<RefCell<dyn HostObject>> = Rc::new(RefCell::new(Point { x: 1.0, y: 2.0 }));
+        let name = &scanner::Scanner::new("x").parse()[0];
+        let _borrow = object.borrow_mut();
+
+        assert_eq!(
+            get(&object, name).unwrap_err().message,
+            "can't access 'x' while the object is in use"
+        );
+        assert!(set(&object, name, Value::Num(3.0)).is_err());
+    }
+}
@@ -1 +1,1269 @@
+use super::ast;
+use super::callable::{self, Callable};
+use super::class::{Class, Instance};
+use super::environment::Environment;
+use super::host::{self, HostClass, HostObject};
+use super::limits::{Allocation, Budget, HeapUsage, Limit, Limits, MAX_CALL_DEPTH};
+use super::native::NativeFunction;
+use super::token;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::io::Write;
+use std::mem;
+use std::panic;
+use std::rc::Rc;
+use std::thread;
 
+// Native stack for running Lox code. Every Lox call recurses through the
+// evaluator, taking up to around 40 KiB in debug builds, so this holds
+// `MAX_CALL_DEPTH` calls with room to spare. Pages are only committed as
+// they are used.
+const STACK_SIZE: usize = 256 << 20;
+
+#[derive(Debug, Clone)]
+pub enum Value {
+    Str(String),
+    Bool(bool),
+    Num(f64),
+    Function(Rc<callable::Function>),
+    Native(Rc<callable::Native>),
+    Class(Rc<Class>),
+    Instance(Rc<RefCell<Instance>>),
+    HostClass(Rc<HostClass>),
+    Host(Rc<RefCell<dyn HostObject>>),
+    None,
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Str(l), Value::Str(r)) => l == r,
+            (Value::Bool(l), Value::Bool(r)) => l == r,
+            (Value::Num(l), Value::Num(r)) => l == r,
+            (Value::Function(l), Value::Function(r)) => Rc::ptr_eq(l, r),
+            (Value::Native(l), Value::Native(r)) => Rc::ptr_eq(l, r),
+            (Value::Class(l), Value::Class(r)) => Rc::ptr_eq(l, r),
+            (Value::Instance(l), Value::Instance(r)) => Rc::ptr_eq(l, r),
+            (Value::HostClass(l), Value::HostClass(r)) => Rc::ptr_eq(l, r),
+            // An object that is in use can only be compared by identity.
+            (Value::Host(l), Value::Host(r)) => {
+                Rc::ptr_eq(l, r)
+                    || match (l.try_borrow(), r.try_borrow()) {
+                        (Ok(l), Ok(r)) => l.equals(&*r),
+                        _ => false,
+                    }
+            }
+            (Value::None, Value::None) => true,
+            _ => false,
+        }
+    }
+}
+
+impl Value {
+    fn is_truthy(&self) -> bool {
+        !matches!(self, Value::None | Value::Bool(false))
+    }
+
+    // Approximate bytes the value takes where it is stored. Strings are
+    // copied with the value, so their contents count wherever they are kept.
+    pub(crate) fn size(&self) -> usize {
+        let contents = match self {
+            Value::Str(s) => s.len(),
+            _ => 0,
+        };
+        mem::size_of::<Value>() + contents
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Str(_) => "string",
+            Value::Bool(_) => "boolean",
+            Value::Num(_) => "number",
+            Value::Function(_) | Value::Native(_) => "function",
+            Value::Class(_) | Value::HostClass(_) => "class",
+            Value::Instance(_) | Value::Host(_) => "instance",
+            Value::None => "nil",
+        }
+    }
+}
+
+impl From<token::Value> for Value {
+    fn from(value: token::Value) -> Self {
+        match value {
+            token::Value::Str(s) => Value::Str(s),
+            token::Value::Bool(b) => Value::Bool(b),
+            token::Value::Num(n) => Value::Num(n),
+            token::Value::None => Value::None,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Str(s) => write!(f, "{}", s),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Num(n) => write!(f, "{}", n),
+            Value::Function(func) => write!(f, "{}", func),
+            Value::Native(native) => write!(f, "{}", native),
+            Value::Class(class) => write!(f, "{}", class),
+            Value::HostClass(class) => write!(f, "{}", class),
+            Value::Instance(instance) => write!(f, "{}", instance.borrow()),
+            // The class name can't be read while one of its methods runs.
+            Value::Host(object) => match object.try_borrow() {
+                Ok(object) => write!(f, "{}", object.display()),
+                Err(_) => write!(f, "host instance"),
+            },
+            Value::None => write!(f, "nil"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct RuntimeError {
+    // Missing for errors raised outside any source, such as a bad call
+    // made through the embedding API.
+    pub line: Option<usize>,
+    // The source the error was raised for, when a token is at hand.
+    pub span: Option<token::Span>,
+    pub message: String,
+    pub kind: RuntimeErrorKind,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum RuntimeErrorKind {
+    Error,
+    // A resource limit stopped the program; see `limits::Limits`.
+    LimitExceeded(Limit),
+}
+
+impl RuntimeError {
+    pub fn new(token: &token::Token, message: &str) -> Self {
+        RuntimeError::on_line(token.span.line, message).at(token.span)
+    }
+
+    pub fn on_line(line: usize, message: &str) -> Self {
+        RuntimeError {
+            line: Some(line),
+            ..RuntimeError::unlocated(message)
+        }
+    }
+
+    pub fn unlocated(message: &str) -> Self {
+        RuntimeError {
+            line: None,
+            span: None,
+            message: message.to_owned(),
+            kind: RuntimeErrorKind::Error,
+        }
+    }
+
+    pub fn limit_exceeded(line: usize, limit: Limit) -> Self {
+        RuntimeError {
+            line: Some(line),
+            span: None,
+            message: limit.to_string(),
+            kind: RuntimeErrorKind::LimitExceeded(limit),
+        }
+    }
+
+    pub fn at(self, span: token::Span) -> Self {
+        RuntimeError {
+            line: Some(span.line),
+            span: Some(span),
+            ..self
+        }
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "line {}: {}", line, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+// Hands the interpreter's values, which are not `Send`, to the thread from
+// `Interpreter::on_stack` and back.
+struct Unshared<T>(T);
+
+// SAFETY: `on_stack` blocks the calling thread until the interpreter's
+// thread has finished, so what this wraps is only used by one thread at a
+// time, and spawning and joining order the two threads' accesses. Nothing
+// the interpreter holds depends on which thread it runs on.
+unsafe impl<T> Send for Unshared<T> {}
+
+impl<T> Unshared<T> {
+    fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+pub(crate) enum Unwind {
+    Error(RuntimeError),
+    Return(Value),
+}
+
+impl From<RuntimeError> for Unwind {
+    fn from(err: RuntimeError) -> Self {
+        Unwind::Error(err)
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct OutputBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl OutputBuffer {
+    pub fn new() -> Self {
+        OutputBuffer::default()
+    }
+
+    pub fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.0.borrow()).into_owned()
+    }
+}
+
+impl Write for OutputBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+pub struct Interpreter {
+    output: Box<dyn Write>,
+    globals: Rc<RefCell<Environment>>,
+    environment: Rc<RefCell<Environment>>,
+    locals: HashMap<usize, usize>,
+    budget: Budget,
+    depth: usize,
+    heap: HeapUsage,
+    // The latest line seen, for errors raised where no token is at hand.
+    line: usize,
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Interpreter::new()
+    }
+}
+
+impl Interpreter {
+    // Public
+    pub fn new() -> Self {
+        Interpreter::with_output(Box::new(io::stdout()))
+    }
+
+    pub fn with_output(output: Box<dyn Write>) -> Self {
+        let heap = HeapUsage::default();
+        let globals = Environment::new().tracked(heap.allocate(mem::size_of::<Environment>()));
+        let globals = Rc::new(RefCell::new(globals));
+        for native in callable::natives() {
+            globals
+                .borrow_mut()
+                .define(&native.name.clone(), Value::Native(Rc::new(native)));
+        }
+
+        Interpreter {
+            output,
+            globals: globals.clone(),
+            environment: globals,
+            locals: HashMap::new(),
+            budget: Budget::default(),
+            depth: 0,
+            heap,
+            line: 0,
+        }
+    }
+
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.budget = Budget::new(limits);
+    }
+
+    // Restarts the step count and the deadline. `interpret` does this itself;
+    // hosts calling into Lox directly do it before each call.
+    pub fn start_budget(&mut self) {
+        self.budget.start();
+    }
+
+    pub fn resolve(&mut self, locals: HashMap<usize, usize>) {
+        self.locals.extend(locals);
+    }
+
+    pub fn interpret(&mut self, statements: &[ast::Stmt]) -> Result<(), RuntimeError> {
+        self.budget.start();
+        self.on_stack(|interpreter| {
+            for s in statements {
+                match interpreter.execute(s) {
+                    Ok(()) => (),
+                    Err(Unwind::Error(err)) => return Err(err),
+                    Err(Unwind::Return(_)) => return Ok(()),
+                }
+            }
+            Ok(())
+        })
+    }
+
+    pub fn evaluate(&mut self, e: &ast::Expr) -> Result<Value, RuntimeError> {
+        self.on_stack(|interpreter| interpreter.eval(e))
+    }
+
+    pub fn get_global(&self, name: &str) -> Option<Value> {
+        self.globals.borrow().get_local(name)
+    }
+
+    pub fn define_global(&mut self, name: &str, value: Value) {
+        self.globals.borrow_mut().define(name, value);
+    }
+
+    // Calls a function, native or class with already evaluated arguments.
+    // `line` is reported if the callee or the argument count is wrong.
+    pub fn call_value(
+        &mut self,
+        callee: &Value,
+        arguments: Vec<Value>,
+        line: Option<usize>,
+    ) -> Result<Value, RuntimeError> {
+        self.on_stack(|interpreter| interpreter.call_at(callee, arguments, line, None))
+    }
+
+    fn call_at(
+        &mut self,
+        callee: &Value,
+        arguments: Vec<Value>,
+        line: Option<usize>,
+        span: Option<token::Span>,
+    ) -> Result<Value, RuntimeError> {
+        let at = |err: RuntimeError| match span {
+            Some(span) => err.at(span),
+            None => RuntimeError { line, ..err },
+        };
+        let error = |message: &str| at(RuntimeError::unlocated(message));
+
+        let arity = |expected: usize| {
+            if arguments.len() == expected {
+                return Ok(());
+            }
+            Err(error(&format!(
+                "expected {} arguments but got {}",
+                expected,
+                arguments.len()
+            )))
+        };
+        let native = |name: &str, function: &dyn NativeFunction| {
+            arity(function.arity())?;
+            function
+                .call(&arguments)
+                .map_err(|err| error(&err.describe(name)))
+        };
+
+        let function: &dyn Callable = match callee {
+            Value::Function(f) => f.as_ref(),
+            Value::Class(c) => c,
+            Value::Native(n) => return native(&n.name, n.function.as_ref()),
+            Value::HostClass(c) => return native(&c.name, c.as_ref()),
+            _ => return Err(error("can only call functions and classes")),
+        };
+
+        arity(function.arity())?;
+
+        self.budget
+            .check_call_depth(self.depth + 1)
+            .map_err(|limit| {
+                at(RuntimeError {
+                    kind: RuntimeErrorKind::LimitExceeded(limit),
+                    ..RuntimeError::unlocated(&limit.to_string())
+                })
+            })?;
+        if self.depth >= MAX_CALL_DEPTH {
+            return Err(error("stack overflow"));
+        }
+        self.depth += 1;
+        let result = function.call(self, arguments);
+        self.depth -= 1;
+        result
+    }
+
+    fn eval(&mut self, e: &ast::Expr) -> Result<Value, RuntimeError> {
+        if let Some(line) = expr_line(e) {
+            self.line = line;
+        }
+        self.step()?;
+
+        match e {
+            ast::Expr::Assign(a) => {
+                let value = self.eval(&a.value)?;
+                match self.locals.get(&a.id) {
+                    Some(distance) => Environment::assign_at(
+                        &self.environment,
+                        *distance,
+                        &a.name,
+                        value.clone(),
+                    )?,
+                    None => self.globals.borrow_mut().assign(&a.name, value.clone())?,
+                }
+                Ok(value)
+            }
+            ast::Expr::Binary(b) => self.binary(b),
+            ast::Expr::Call(c) => self.call(c),
+            ast::Expr::Get(g) => match self.eval(&g.object)? {
+                Value::Instance(instance) => Instance::get(&instance, &g.name),
+                Value::Host(object) => host::get(&object, &g.name),
+                _ => Err(RuntimeError::new(&g.name, "only instances have properties")),
+            },
+            ast::Expr::Grouping(g) => self.eval(&g.exp),
+            ast::Expr::Literal(l) => Ok(Value::from(l.value.clone())),
+            ast::Expr::Logical(l) => {
+                let left = self.eval(&l.left)?;
+                let short_circuit = match l.op.typ {
+                    token::Type::Or => left.is_truthy(),
+                    _ => !left.is_truthy(),
+                };
+
+                if short_circuit {
+                    Ok(left)
+                } else {
+                    self.eval(&l.right)
+                }
+            }
+            ast::Expr::Set(s) => {
+                let object = self.eval(&s.object)?;
+                let value = self.eval(&s.value)?;
+                match object {
+                    Value::Instance(instance) => {
+                        instance.borrow_mut().set(&s.name, value.clone());
+                        Ok(value)
+                    }
+                    Value::Host(object) => {
+                        host::set(&object, &s.name, value.clone())?;
+                        Ok(value)
+                    }
+                    _ => Err(RuntimeError::new(&s.name, "only instances have fields")),
+                }
+            }
+            ast::Expr::Super(s) => self.super_method(s),
+            ast::Expr::This(t) => self.look_up_variable(t.id, &t.keyword),
+            ast::Expr::Unary(u) => self.unary(u),
+            ast::Expr::Variable(v) => self.look_up_variable(v.id, &v.name),
+            ast::Expr::None => Ok(Value::None),
+        }
+    }
+
+    // Execute
+    fn execute(&mut self, s: &ast::Stmt) -> Result<(), Unwind> {
+        if let Some(line) = stmt_line(s) {
+            self.line = line;
+        }
+        self.step()?;
+
+        match s {
+            ast::Stmt::Block(b) => {
+                let environment = Environment::with_enclosing(self.environment.clone())
+                    .tracked(self.allocate::<Environment>());
+                self.execute_block(&b.statements, Rc::new(RefCell::new(environment)))
+            }
+            ast::Stmt::Class(c) => {
+                let superclass = match &c.superclass {
+                    Some(s) => match self.look_up_variable(s.id, &s.name)? {
+                        Value::Class(class) => Some(class),
+                        Value::HostClass(class) => {
+                            let message = format!("can't inherit from host class '{}'", class.name);
+                            return Err(RuntimeError::new(&s.name, &message).into());
+                        }
+                        _ => {
+                            return Err(
+                                RuntimeError::new(&s.name, "superclass must be a class").into()
+                            )
+                        }
+                    },
+                    None => None,
+                };
+
+                self.environment
+                    .borrow_mut()
+                    .define(c.name.name(), Value::None);
+
+                let enclosing = self.environment.clone();
+                if let Some(superclass) = &superclass {
+                    let mut environment = Environment::with_enclosing(enclosing.clone());
+                    environment.define("super", Value::Class(superclass.clone()));
+                    self.environment = Rc::new(RefCell::new(environment));
+                }
+
+                let mut methods = HashMap::new();
+                for method in &c.methods {
+                    let function = callable::Function::new(
+                        method.clone(),
+                        self.environment.clone(),
+                        method.name.name() == "init",
+                    );
+                    methods.insert(method.name.name().to_owned(), Rc::new(function));
+                }
+
+                let class = Class::new(c.name.name(), superclass, methods);
+                self.environment = enclosing;
+                self.environment
+                    .borrow_mut()
+                    .assign(&c.name, Value::Class(Rc::new(class)))?;
+                Ok(())
+            }
+            ast::Stmt::Expression(e) => {
+                self.eval(&e.exp)?;
+                Ok(())
+            }
+            ast::Stmt::Function(f) => {
+                let function = callable::Function::new(f.clone(), self.environment.clone(), false);
+                self.environment
+                    .borrow_mut()
+                    .define(f.name.name(), Value::Function(Rc::new(function)));
+                Ok(())
+            }
+            ast::Stmt::If(i) => {
+                if self.eval(&i.condition)?.is_truthy() {
+                    self.execute(&i.then_branch)
+                } else if let Some(else_branch) = &i.else_branch {
+                    self.execute(else_branch)
+                } else {
+                    Ok(())
+                }
+            }
+            ast::Stmt::Print(p) => {
+                let value = self.eval(&p.exp)?;
+                writeln!(self.output, "{}", value)
+                    .map_err(|err| RuntimeError::new(&p.keyword, &err.to_string()).into())
+            }
+            ast::Stmt::Return(r) => {
+                let value = match &r.value {
+                    Some(value) => self.eval(value)?,
+                    None => Value::None,
+                };
+                Err(Unwind::Return(value))
+            }
+            ast::Stmt::Var(v) => {
+                let value = match &v.initializer {
+                    Some(initializer) => self.eval(initializer)?,
+                    None => Value::None,
+                };
+                self.environment.borrow_mut().define(v.name.name(), value);
+                Ok(())
+            }
+            ast::Stmt::While(w) => {
+                while self.eval(&w.condition)?.is_truthy() {
+                    self.execute(&w.body)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    pub(crate) fn execute_block(
+        &mut self,
+        statements: &[ast::Stmt],
+        environment: Rc<RefCell<Environment>>,
+    ) -> Result<(), Unwind> {
+        let previous = std::mem::replace(&mut self.environment, environment);
+        let result = statements.iter().try_for_each(|s| self.execute(s));
+        self.environment = previous;
+        result
+    }
+
+    // Runs `f` on a thread with `STACK_SIZE` of stack and waits for it, so
+    // that deep recursion doesn't depend on the caller's stack.
+    fn on_stack<R>(&mut self, f: impl FnOnce(&mut Interpreter) -> R) -> R {
+        let task = Unshared((self, f));
+        let result = thread::scope(|scope| {
+            thread::Builder::new()
+                .name("lox".to_owned())
+                .stack_size(STACK_SIZE)
+                .spawn_scoped(scope, move || {
+                    let (interpreter, f) = task.into_inner();
+                    Unshared(f(interpreter))
+                })
+                .expect("failed to start the interpreter thread")
+                .join()
+        });
+        match result {
+            Ok(result) => result.into_inner(),
+            Err(payload) => panic::resume_unwind(payload),
+        }
+    }
+
+    // Limits
+    fn step(&mut self) -> Result<(), RuntimeError> {
+        self.budget
+            .step()
+            .and_then(|()| self.budget.check_heap(self.heap.bytes()))
+            .map_err(|limit| RuntimeError::limit_exceeded(self.line, limit))
+    }
+
+    pub(crate) fn allocate<T>(&self) -> Allocation {
+        self.heap.allocate(mem::size_of::<T>())
+    }
+
+    // Evaluate
+    fn binary(&mut self, b: &ast::Binary) -> Result<Value, RuntimeError> {
+        let left = self.eval(&b.left)?;
+        let right = self.eval(&b.right)?;
+
+        match b.op.typ {
+            token::Type::EqualEqual => Ok(Value::Bool(left == right)),
+            token::Type::BangEqual => Ok(Value::Bool(left != right)),
+            token::Type::Plus => match (left, right) {
+                (Value::Num(l), Value::Num(r)) => Ok(Value::Num(l + r)),
+                (Value::Str(l), Value::Str(r)) => {
+                    self.budget
+                        .check_heap(self.heap.bytes() + l.len() + r.len())
+                        .map_err(|limit| {
+                            RuntimeError::limit_exceeded(b.op.span.line, limit).at(b.op.span)
+                        })?;
+                    Ok(Value::Str(l + &r))
+                }
+                _ => Err(RuntimeError::new(
+                    &b.op,
+                    "operands must be two numbers or two strings",
+                )),
+            },
+            token::Type::Minus => {
+                let (l, r) = Self::number_operands(&b.op, &left, &right)?;
+                Ok(Value::Num(l - r))
+            }
+            token::Type::Star => {
+                let (l, r) = Self::number_operands(&b.op, &left, &right)?;
+                Ok(Value::Num(l * r))
+            }
+            token::Type::Slash => {
+                let (l, r) = Self::number_operands(&b.op, &left, &right)?;
+                Ok(Value::Num(l / r))
+            }
+            token::Type::Greater => {
+                let (l, r) = Self::number_operands(&b.op, &left, &right)?;
+                Ok(Value::Bool(l > r))
+            }
+            token::Type::GreaterEqual => {
+                let (l, r) = Self::number_operands(&b.op, &left, &right)?;
+                Ok(Value::Bool(l >= r))
+            }
+            token::Type::Less => {
+                let (l, r) = Self::number_operands(&b.op, &left, &right)?;
+                Ok(Value::Bool(l < r))
+            }
+            token::Type::LessEqual => {
+                let (l, r) = Self::number_operands(&b.op, &left, &right)?;
+                Ok(Value::Bool(l <= r))
+            }
+            _ => Err(RuntimeError::new(&b.op, "unknown binary operator")),
+        }
+    }
+
+    fn call(&mut self, c: &ast::Call) -> Result<Value, RuntimeError> {
+        let callee = self.eval(&c.callee)?;
+
+        let mut arguments = Vec::with_capacity(c.arguments.len());
+        for argument in &c.arguments {
+            arguments.push(self.eval(argument)?);
+        }
+
+        self.call_at(&callee, arguments, None, Some(c.paren.span))
+    }
+
+    fn super_method(&mut self, s: &ast::Super) -> Result<Value, RuntimeError> {
+        let distance = self.locals.get(&s.id).copied().unwrap_or(0);
+        let superclass = Environment::ancestor(&self.environment, distance)
+            .borrow()
+            .get_local("super");
+        let object = Environment::ancestor(&self.environment, distance.saturating_sub(1))
+            .borrow()
+            .get_local("this");
+
+        match (superclass, object) {
+            (Some(Value::Class(superclass)), Some(Value::Instance(object))) => {
+                match superclass.find_method(s.method.name()) {
+                    Some(method) => Ok(Value::Function(Rc::new(method.bind(object)))),
+                    None => Err(RuntimeError::new(
+                        &s.method,
+                        &format!("undefined property '{}'", s.method.name()),
+                    )),
+                }
+            }
+            _ => Err(RuntimeError::new(
+                &s.keyword,
+                "can't use 'super' in a class with no superclass",
+            )),
+        }
+    }
+
+    fn unary(&mut self, u: &ast::Unary) -> Result<Value, RuntimeError> {
+        let right = self.eval(&u.right)?;
+
+        match u.op.typ {
+            token::Type::Bang => Ok(Value::Bool(!right.is_truthy())),
+            token::Type::Minus => match right {
+                Value::Num(n) => Ok(Value::Num(-n)),
+                _ => Err(RuntimeError::new(&u.op, "operand must be a number")),
+            },
+            _ => Err(RuntimeError::new(&u.op, "unknown unary operator")),
+        }
+    }
+
+    // Utilities
+
+    fn look_up_variable(&self, id: usize, name: &token::Token) -> Result<Value, RuntimeError> {
+        match self.locals.get(&id) {
+            Some(distance) => Environment::get_at(&self.environment, *distance, name),
+            None => self.globals.borrow().get(name),
+        }
+    }
+
+    fn number_operands(
+        op: &token::Token,
+        left: &Value,
+        right: &Value,
+    ) -> Result<(f64, f64), RuntimeError> {
+        match (left, right) {
+            (Value::Num(l), Value::Num(r)) => Ok((*l, *r)),
+            _ => Err(RuntimeError::new(op, "operands must be numbers")),
+        }
+    }
+}
+
+// The line of the token an expression or statement starts from, if any.
+fn expr_line(e: &ast::Expr) -> Option<usize> {
+    let token = match e {
+        ast::Expr::Assign(a) => &a.name,
+        ast::Expr::Binary(b) => &b.op,
+        ast::Expr::Call(c) => &c.paren,
+        ast::Expr::Get(g) => &g.name,
+        ast::Expr::Logical(l) => &l.op,
+        ast::Expr::Set(s) => &s.name,
+        ast::Expr::Super(s) => &s.keyword,
+        ast::Expr::This(t) => &t.keyword,
+        ast::Expr::Unary(u) => &u.op,
+        ast::Expr::Variable(v) => &v.name,
+        ast::Expr::Grouping(_) | ast::Expr::Literal(_) | ast::Expr::None => return None,
+    };
+    Some(token.span.line)
+}
+
+fn stmt_line(s: &ast::Stmt) -> Option<usize> {
+    let token = match s {
+        ast::Stmt::Class(c) => &c.name,
+        ast::Stmt::Function(f) => &f.name,
+        ast::Stmt::Print(p) => &p.keyword,
+        ast::Stmt::Return(r) => &r.keyword,
+        ast::Stmt::Var(v) => &v.name,
+        ast::Stmt::Block(_) | ast::Stmt::Expression(_) | ast::Stmt::If(_) | ast::Stmt::While(_) => {
+            return None
+        }
+    };
+    Some(token.span.line)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::super::parser;
+    use super::super::resolver;
+    use super::super::scanner;
+    use super::*;
+
+    // Most tests only check the line; `errors_point_at_tokens` checks spans.
+    fn line_only(err: RuntimeError) -> RuntimeError {
+        RuntimeError { span: None, ..err }
+    }
+
+    fn evaluate(source: &str) -> Result<Value, RuntimeError> {
+        let tokens = scanner::Scanner::new(&format!("{};", source)).parse();
+        let (statements, errors) = parser::Parser::new(tokens).parse();
+        assert!(errors.is_empty(), "{:?}", errors);
+        match &statements[0] {
+            ast::Stmt::Expression(e) => Interpreter::new().evaluate(&e.exp).map_err(line_only),
+            s => panic!("expected an expression statement, got {:?}", s),
+        }
+    }
+
+    fn run(source: &str) -> Result<String, RuntimeError> {
+        let tokens = scanner::Scanner::new(source).parse();
+        let (statements, errors) = parser::Parser::new(tokens).parse();
+        assert!(errors.is_empty(), "{:?}", errors);
+        let locals = resolver::Resolver::new().resolve(&statements).unwrap();
+        let output = OutputBuffer::new();
+        let mut interpreter = Interpreter::with_output(Box::new(output.clone()));
+        interpreter.resolve(locals);
+        interpreter.interpret(&statements).map_err(line_only)?;
+        Ok(output.contents())
+    }
+
+    #[test]
+    fn arithmetic() {
+        assert_eq!(evaluate("1 + 2 * 3"), Ok(Value::Num(7.0)));
+        assert_eq!(evaluate("(1 + 2) * 3"), Ok(Value::Num(9.0)));
+        assert_eq!(evaluate("10 / 4 - 1"), Ok(Value::Num(1.5)));
+        assert_eq!(evaluate("-(3)"), Ok(Value::Num(-3.0)));
+    }
+
+    #[test]
+    fn comparison_and_equality() {
+        assert_eq!(evaluate("1 < 2"), Ok(Value::Bool(true)));
+        assert_eq!(evaluate("2 <= 1"), Ok(Value::Bool(false)));
+        assert_eq!(evaluate("1 == 1"), Ok(Value::Bool(true)));
+        assert_eq!(evaluate("\"a\" != \"a\""), Ok(Value::Bool(false)));
+        assert_eq!(evaluate("nil == nil"), Ok(Value::Bool(true)));
+        assert_eq!(evaluate("nil == false"), Ok(Value::Bool(false)));
+        assert_eq!(evaluate("1 == \"1\""), Ok(Value::Bool(false)));
+    }
+
+    #[test]
+    fn truthiness() {
+        assert_eq!(evaluate("!nil"), Ok(Value::Bool(true)));
+        assert_eq!(evaluate("!false"), Ok(Value::Bool(true)));
+        assert_eq!(evaluate("!0"), Ok(Value::Bool(false)));
+        assert_eq!(evaluate("!\"\""), Ok(Value::Bool(false)));
+    }
+
+    #[test]
+    fn string_concatenation() {
+        assert_eq!(
+            evaluate("\"foo\" + \"bar\""),
+            Ok(Value::Str("foobar".to_owned()))
+        );
+    }
+
+    #[test]
+    fn runtime_errors() {
+        assert_eq!(
+            evaluate("\"a\" - 1"),
+            Err(RuntimeError::on_line(1, "operands must be numbers"))
+        );
+        assert_eq!(
+            evaluate("1 +\n\"a\""),
+            Err(RuntimeError::on_line(
+                1,
+                "operands must be two numbers or two strings"
+            ))
+        );
+        assert_eq!(
+            evaluate("-\"a\""),
+            Err(RuntimeError::on_line(1, "operand must be a number"))
+        );
+    }
+
+    #[test]
+    fn print_statements() {
+        assert_eq!(
+            run("print 1 + 2; print \"a\" + \"b\"; { print nil; print !true; }"),
+            Ok("3\nab\nnil\nfalse\n".to_owned())
+        );
+    }
+
+    #[test]
+    fn runtime_error_stops_execution() {
+        assert_eq!(
+            run("print 1;\nprint -nil;\nprint 2;"),
+            Err(RuntimeError::on_line(2, "operand must be a number"))
+        );
+    }
+
+    #[test]
+    fn variables_and_assignment() {
+        assert_eq!(
+            run("var a = 1; var b; print a; print b; a = b = 2; print a + b;"),
+            Ok("1\nnil\n4\n".to_owned())
+        );
+        assert_eq!(
+            run("var a = 1; var a = a + 1; print a;"),
+            Ok("2\n".to_owned())
+        );
+    }
+
+    #[test]
+    fn block_scopes() {
+        assert_eq!(
+            run(r#"
+                var a = "global a";
+                var b = "global b";
+                {
+                    var a = "outer a";
+                    {
+                        var a = "inner a";
+                        print a;
+                        print b;
+                        b = "assigned b";
+                    }
+                    print a;
+                }
+                print a;
+                print b;
+            "#),
+            Ok("inner a\nglobal b\nouter a\nglobal a\nassigned b\n".to_owned())
+        );
+    }
+
+    #[test]
+    fn undefined_variables() {
+        assert_eq!(
+            run("print 1;\nprint missing;"),
+            Err(RuntimeError::on_line(2, "undefined variable 'missing'"))
+        );
+        assert_eq!(
+            run("{ var a = 1; }\n\na = 2;"),
+            Err(RuntimeError::on_line(3, "undefined variable 'a'"))
+        );
+    }
+
+    #[test]
+    fn conditionals() {
+        assert_eq!(
+            run("if (1 < 2) print \"then\"; else print \"else\"; if (nil) print 1; else print 2; if (false) print 3;"),
+            Ok("then\n2\n".to_owned())
+        );
+    }
+
+    #[test]
+    fn logical_operators_return_operands() {
+        assert_eq!(evaluate("\"hi\" or 2"), Ok(Value::Str("hi".to_owned())));
+        assert_eq!(evaluate("nil or \"yes\""), Ok(Value::Str("yes".to_owned())));
+        assert_eq!(evaluate("nil and 1"), Ok(Value::None));
+        assert_eq!(evaluate("1 and 2"), Ok(Value::Num(2.0)));
+    }
+
+    #[test]
+    fn logical_operators_short_circuit() {
+        assert_eq!(
+            run("var a = 0; false and (a = 1); true or (a = 2); print a; true and (a = 3); print a;"),
+            Ok("0\n3\n".to_owned())
+        );
+    }
+
+    #[test]
+    fn loops() {
+        assert_eq!(
+            run("var i = 0; while (i < 3) { print i; i = i + 1; }"),
+            Ok("0\n1\n2\n".to_owned())
+        );
+        assert_eq!(
+            run("var a = 0; var b = 1; for (var i = 0; i < 5; i = i + 1) { print a; var t = a; a = b; b = t + b; }"),
+            Ok("0\n1\n1\n2\n3\n".to_owned())
+        );
+        assert_eq!(
+            run("for (var i = 0; i < 1; i = i + 1) {} print i;"),
+            Err(RuntimeError::on_line(1, "undefined variable 'i'"))
+        );
+    }
+
+    #[test]
+    fn functions() {
+        assert_eq!(
+            run(r#"
+                fun sayHi(first, last) {
+                    print "Hi, " + first + " " + last + "!";
+                }
+                sayHi("Dear", "Reader");
+                print sayHi;
+                print sayHi("a", "b");
+            "#),
+            Ok("Hi, Dear Reader!\n<fn sayHi>\nHi, a b!\nnil\n".to_owned())
+        );
+    }
+
+    #[test]
+    fn recursion_and_return() {
+        assert_eq!(
+            run(r#"
+                fun fib(n) {
+                    if (n <= 1) return n;
+                    return fib(n - 2) + fib(n - 1);
+                }
+                for (var i = 0; i < 8; i = i + 1) {
+                    print fib(i);
+                }
+            "#),
+            Ok("0\n1\n1\n2\n3\n5\n8\n13\n".to_owned())
+        );
+        assert_eq!(
+            run("fun f() { while (true) { return \"done\"; } } print f();"),
+            Ok("done\n".to_owned())
+        );
+    }
+
+    #[test]
+    fn local_functions_capture_their_environment() {
+        assert_eq!(
+            run(r#"
+                fun makeCounter() {
+                    var i = 0;
+                    fun count() {
+                        i = i + 1;
+                        print i;
+                    }
+                    return count;
+                }
+                var counter = makeCounter();
+                counter();
+                counter();
+            "#),
+            Ok("1\n2\n".to_owned())
+        );
+    }
+
+    #[test]
+    fn native_functions() {
+        assert_eq!(
+            run("var t = clock(); print t > 0; print clock;"),
+            Ok("true\n<native fn>\n".to_owned())
+        );
+    }
+
+    #[test]
+    fn call_errors() {
+        assert_eq!(
+            run("var a = \"not a function\";\na();"),
+            Err(RuntimeError::on_line(
+                2,
+                "can only call functions and classes"
+            ))
+        );
+        assert_eq!(
+            run("fun f(a, b) {}\nf(1);"),
+            Err(RuntimeError::on_line(2, "expected 2 arguments but got 1"))
+        );
+        assert_eq!(
+            run("clock(1);"),
+            Err(RuntimeError::on_line(1, "expected 0 arguments but got 1"))
+        );
+    }
+
+    #[test]
+    fn closures_keep_their_definition_site_binding() {
+        assert_eq!(
+            run(r#"
+                var a = "global";
+                {
+                    fun showA() {
+                        print a;
+                    }
+
+                    showA();
+                    var a = "block";
+                    showA();
+                    print a;
+                }
+            "#),
+            Ok("global\nglobal\nblock\n".to_owned())
+        );
+    }
+
+    #[test]
+    fn closures_capture_independent_bindings() {
+        assert_eq!(
+            run(r#"
+                fun makeAdder(n) {
+                    fun add(x) {
+                        return x + n;
+                    }
+                    return add;
+                }
+                var addOne = makeAdder(1);
+                var addTen = makeAdder(10);
+                print addOne(1);
+                print addTen(1);
+            "#),
+            Ok("2\n11\n".to_owned())
+        );
+    }
+
+    #[test]
+    fn classes_and_instances() {
+        assert_eq!(
+            run(r#"
+                class Bagel {}
+                var bagel = Bagel();
+                print Bagel;
+                print bagel;
+                bagel.flavor = "plain";
+                print bagel.flavor;
+                print bagel == bagel;
+                print bagel == Bagel();
+            "#),
+            Ok("Bagel\nBagel instance\nplain\ntrue\nfalse\n".to_owned())
+        );
+    }
+
+    #[test]
+    fn methods_bind_this() {
+        assert_eq!(
+            run(r#"
+                class Cake {
+                    taste() {
+                        var adjective = "delicious";
+                        print "The " + this.flavor + " cake is " + adjective + "!";
+                    }
+                }
+                var cake = Cake();
+                cake.flavor = "German chocolate";
+                var taste = cake.taste;
+                taste();
+
+                class Person {
+                    sayName() {
+                        print this.name;
+                    }
+                }
+                var jane = Person();
+                jane.name = "Jane";
+                var bill = Person();
+                bill.name = "Bill";
+                bill.sayName = jane.sayName;
+                bill.sayName();
+            "#),
+            Ok("The German chocolate cake is delicious!\nJane\n".to_owned())
+        );
+    }
+
+    #[test]
+    fn initializers() {
+        assert_eq!(
+            run(r#"
+                class Point {
+                    init(x, y) {
+                        this.x = x;
+                        this.y = y;
+                        if (x == 0) return;
+                        this.y = y + 1;
+                    }
+                    sum() {
+                        return this.x + this.y;
+                    }
+                }
+                var p = Point(1, 2);
+                print p.sum();
+                print Point(0, 2).sum();
+                print p.init(5, 5) == p;
+                print p.sum();
+            "#),
+            Ok("4\n2\ntrue\n11\n".to_owned())
+        );
+    }
+
+    #[test]
+    fn class_errors() {
+        assert_eq!(
+            run("class A { init(a) {} }\nA();"),
+            Err(RuntimeError::on_line(2, "expected 1 arguments but got 0"))
+        );
+        assert_eq!(
+            run("class A {}\nprint A().missing;"),
+            Err(RuntimeError::on_line(2, "undefined property 'missing'"))
+        );
+        assert_eq!(
+            run("var a = 1;\na.b = 2;"),
+            Err(RuntimeError::on_line(2, "only instances have fields"))
+        );
+        assert_eq!(
+            run("\"str\".length;"),
+            Err(RuntimeError::on_line(1, "only instances have properties"))
+        );
+    }
+
+    #[test]
+    fn inheritance() {
+        assert_eq!(
+            run(r#"
+                class Doughnut {
+                    cook() {
+                        print "Fry until golden brown.";
+                    }
+                    name() {
+                        return "doughnut";
+                    }
+                }
+                class BostonCream < Doughnut {
+                    cook() {
+                        super.cook();
+                        print "Pipe full of custard and coat with chocolate.";
+                    }
+                }
+                BostonCream().cook();
+                print BostonCream().name();
+            "#),
+            Ok("Fry until golden brown.\nPipe full of custard and coat with chocolate.\ndoughnut\n".to_owned())
+        );
+    }
+
+    #[test]
+    fn super_binds_to_the_defining_class() {
+        assert_eq!(
+            run(r#"
+                class A {
+                    method() {
+                        print "A method";
+                    }
+                }
+                class B < A {
+                    method() {
+                        print "B method";
+                    }
+                    test() {
+                        super.method();
+                    }
+                }
+                class C < B {}
+                C().test();
+            "#),
+            Ok("A method\n".to_owned())
+        );
+        assert_eq!(
+            run(r#"
+                class Base {
+                    init(a) {
+                        this.a = a;
+                    }
+                }
+                class Derived < Base {
+                    init(a, b) {
+                        super.init(a);
+                        this.b = b;
+                    }
+                }
+                var d = Derived(1, 2);
+                print d.a + d.b;
+            "#),
+            Ok("3\n".to_owned())
+        );
+    }
+
+    #[test]
+    fn inheritance_errors() {
+        assert_eq!(
+            run("var NotAClass = \"so not a class\";\nclass Subclass < NotAClass {}"),
+            Err(RuntimeError::on_line(2, "superclass must be a class"))
+        );
+        assert_eq!(
+            run("class A {}\nclass B < A {\n  m() { super.missing(); }\n}\nB().m();"),
+            Err(RuntimeError::on_line(3, "undefined property 'missing'"))
+        );
+    }
+
+    #[test]
+    fn errors_point_at_tokens() {
+        let source = "fun f(a) {}\nprint -\"a\";\nf();\nprint nil.x;";
+        let tokens = scanner::Scanner::new(source).parse();
+        let (statements, _) = parser::Parser::new(tokens).parse();
+        let text = |err: RuntimeError| err.span.map(|s| &source[s.start..s.end]);
+
+        let mut interpreter = Interpreter::with_output(Box::new(OutputBuffer::new()));
+        let run = |interpreter: &mut Interpreter, i: usize| {
+            interpreter.interpret(&statements[i..=i]).unwrap_err()
+        };
+        interpreter.interpret(&statements[..1]).unwrap();
+        assert_eq!(text(run(&mut interpreter, 1)), Some("-"));
+        assert_eq!(text(run(&mut interpreter, 2)), Some(")"));
+        assert_eq!(text(run(&mut interpreter, 3)), Some("x"));
+    }
+}
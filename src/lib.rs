@@ -0,0 +1,19 @@
+pub mod ast;
+pub mod backend;
+pub mod callable;
+pub mod chunk;
+pub mod class;
+pub mod compiler;
+pub mod diagnostics;
+pub mod environment;
+pub mod heap;
+pub mod host;
+pub mod interpreter;
+pub mod limits;
+pub mod lox;
+pub mod native;
+pub mod parser;
+pub mod resolver;
+pub mod scanner;
+pub mod token;
+pub mod vm;
@@ -0,0 +1,277 @@
+use std::cell::Cell;
+use std::fmt;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+// How often, in steps, the clock and the cancel flag are read.
+const CHECK_INTERVAL: u64 = 1024;
+
+// How deeply Lox calls may nest on either backend. Going deeper is a
+// "stack overflow" runtime error.
+pub const MAX_CALL_DEPTH: usize = 1024;
+
+// Resource limits for running untrusted programs. `None` leaves a resource
+// unbounded. Steps are tree nodes for the interpreter and instructions for
+// the VM, and the heap is measured in approximate bytes of live objects.
+// A `Cancel` flag ends the run early from another thread.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Limits {
+    pub max_steps: Option<u64>,
+    pub max_call_depth: Option<usize>,
+    pub max_heap: Option<usize>,
+    pub timeout: Option<Duration>,
+    pub cancel: Option<Cancel>,
+}
+
+impl Limits {
+    pub fn unlimited() -> Self {
+        Limits::default()
+    }
+
+    pub fn with_max_steps(mut self, steps: u64) -> Self {
+        self.max_steps = Some(steps);
+        self
+    }
+
+    // Depths past `MAX_CALL_DEPTH` count as `MAX_CALL_DEPTH`, since no
+    // backend nests calls any deeper.
+    pub fn with_max_call_depth(mut self, depth: usize) -> Self {
+        self.max_call_depth = Some(depth);
+        self
+    }
+
+    pub fn with_max_heap(mut self, bytes: usize) -> Self {
+        self.max_heap = Some(bytes);
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn with_cancel(mut self, cancel: Cancel) -> Self {
+        self.cancel = Some(cancel);
+        self
+    }
+}
+
+// Stops a run from another thread, for example when its result is no
+// longer wanted. Clones share the flag.
+#[derive(Debug, Clone, Default)]
+pub struct Cancel(Arc<AtomicBool>);
+
+impl Cancel {
+    pub fn new() -> Self {
+        Cancel::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+impl PartialEq for Cancel {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Limit {
+    Steps,
+    CallDepth,
+    Heap,
+    Deadline,
+    Cancelled,
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Limit::Steps => write!(f, "step limit exceeded"),
+            Limit::CallDepth => write!(f, "call depth limit exceeded"),
+            Limit::Heap => write!(f, "heap limit exceeded"),
+            Limit::Deadline => write!(f, "deadline exceeded"),
+            Limit::Cancelled => write!(f, "run cancelled"),
+        }
+    }
+}
+
+// What one run has used so far. Steps and the deadline restart with each
+// run, while call depth and heap usage are tracked by the backends.
+#[derive(Debug, Default)]
+pub(crate) struct Budget {
+    pub limits: Limits,
+    steps: u64,
+    deadline: Option<Instant>,
+}
+
+impl Budget {
+    pub fn new(limits: Limits) -> Self {
+        Budget {
+            limits,
+            steps: 0,
+            deadline: None,
+        }
+    }
+
+    pub fn start(&mut self) {
+        self.steps = 0;
+        self.deadline = self.limits.timeout.map(|timeout| Instant::now() + timeout);
+    }
+
+    pub fn step(&mut self) -> Result<(), Limit> {
+        self.steps += 1;
+        if self.limits.max_steps.is_some_and(|max| self.steps > max) {
+            return Err(Limit::Steps);
+        }
+        if !self.steps.is_multiple_of(CHECK_INTERVAL) {
+            return Ok(());
+        }
+        if self
+            .deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            return Err(Limit::Deadline);
+        }
+        if self
+            .limits
+            .cancel
+            .as_ref()
+            .is_some_and(Cancel::is_cancelled)
+        {
+            return Err(Limit::Cancelled);
+        }
+        Ok(())
+    }
+
+    pub fn check_call_depth(&self, depth: usize) -> Result<(), Limit> {
+        match self.limits.max_call_depth {
+            Some(max) if depth > max.min(MAX_CALL_DEPTH) => Err(Limit::CallDepth),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn check_heap(&self, bytes: usize) -> Result<(), Limit> {
+        match self.limits.max_heap {
+            Some(max) if bytes > max => Err(Limit::Heap),
+            _ => Ok(()),
+        }
+    }
+}
+
+// Live bytes of the objects that count against the heap limit, for the
+// interpreter, whose values are reference counted rather than collected.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct HeapUsage(Rc<Cell<usize>>);
+
+impl HeapUsage {
+    pub fn bytes(&self) -> usize {
+        self.0.get()
+    }
+
+    pub fn allocate(&self, bytes: usize) -> Allocation {
+        self.0.set(self.0.get() + bytes);
+        Allocation {
+            usage: self.clone(),
+            bytes,
+        }
+    }
+}
+
+// Gives its bytes back to the heap usage when the owning object is dropped.
+#[derive(Debug)]
+pub(crate) struct Allocation {
+    usage: HeapUsage,
+    bytes: usize,
+}
+
+impl Allocation {
+    // Follows the owning object as it grows or shrinks.
+    pub fn resize(&mut self, added: usize, removed: usize) {
+        let usage = &self.usage.0;
+        usage.set(usage.get() + added - removed);
+        self.bytes = self.bytes + added - removed;
+    }
+}
+
+impl Drop for Allocation {
+    fn drop(&mut self) {
+        let usage = &self.usage.0;
+        usage.set(usage.get() - self.bytes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn steps_restart_with_each_run() {
+        let mut budget = Budget::new(Limits::unlimited().with_max_steps(2));
+        budget.start();
+
+        assert_eq!(budget.step(), Ok(()));
+        assert_eq!(budget.step(), Ok(()));
+        assert_eq!(budget.step(), Err(Limit::Steps));
+
+        budget.start();
+        assert_eq!(budget.step(), Ok(()));
+    }
+
+    #[test]
+    fn deadlines() {
+        let mut budget = Budget::new(Limits::unlimited().with_timeout(Duration::ZERO));
+        budget.start();
+
+        let result = (0..CHECK_INTERVAL).try_for_each(|_| budget.step());
+        assert_eq!(result, Err(Limit::Deadline));
+    }
+
+    #[test]
+    fn cancelled_runs_stop() {
+        let cancel = Cancel::new();
+        let mut budget = Budget::new(Limits::unlimited().with_cancel(cancel.clone()));
+        budget.start();
+
+        assert_eq!((0..CHECK_INTERVAL).try_for_each(|_| budget.step()), Ok(()));
+        cancel.cancel();
+        let result = (0..CHECK_INTERVAL).try_for_each(|_| budget.step());
+        assert_eq!(result, Err(Limit::Cancelled));
+    }
+
+    #[test]
+    fn allocations_are_returned_when_dropped() {
+        let usage = HeapUsage::default();
+        let a = usage.allocate(16);
+        let b = usage.allocate(8);
+        assert_eq!(usage.bytes(), 24);
+
+        let mut a = a;
+        a.resize(10, 4);
+        assert_eq!(usage.bytes(), 30);
+
+        drop(a);
+        assert_eq!(usage.bytes(), 8);
+        drop(b);
+        assert_eq!(usage.bytes(), 0);
+    }
+
+    #[test]
+    fn unlimited_budgets_never_fail() {
+        let mut budget = Budget::new(Limits::unlimited());
+        budget.start();
+
+        assert_eq!((0..10_000).try_for_each(|_| budget.step()), Ok(()));
+        assert_eq!(budget.check_call_depth(usize::MAX), Ok(()));
+        assert_eq!(budget.check_heap(usize::MAX), Ok(()));
+    }
+}
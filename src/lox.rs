@@ -0,0 +1,309 @@
+use super::ast;
+use super::backend;
+use super::callable::Native;
+use super::diagnostics::Diagnostic;
+use super::interpreter::Interpreter;
+use super::resolver::Resolver;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::path::Path;
+use std::rc::Rc;
+
+pub use super::host::{HostClass, HostObject};
+pub use super::interpreter::{RuntimeError, RuntimeErrorKind, Value};
+pub use super::limits::{Limit, Limits};
+pub use super::native::{FromLox, IntoLox, IntoNative, NativeError, NativeFunction};
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Compile(Vec<Diagnostic>),
+    Runtime(RuntimeError),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(err) => write!(f, "{}", err),
+            Error::Compile(diagnostics) => {
+                let lines: Vec<String> = diagnostics.iter().map(|d| d.to_string()).collect();
+                write!(f, "{}", lines.join("\n"))
+            }
+            Error::Runtime(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+impl From<RuntimeError> for Error {
+    fn from(err: RuntimeError) -> Self {
+        Error::Runtime(err)
+    }
+}
+
+// A Lox session for embedding in Rust programs. Globals defined by one
+// `eval` stay visible to the next, and to `get_global` and `call`.
+//
+// Embedded programs always run on the tree-walking interpreter. The bytecode
+// VM has no way to register natives, so host classes and objects never reach
+// it. Each `eval` and `call` runs on a thread of its own with a large stack
+// while the caller waits, so natives and host objects must not rely on
+// thread-local state.
+pub struct Lox {
+    interpreter: Interpreter,
+}
+
+impl Default for Lox {
+    fn default() -> Self {
+        Lox::new()
+    }
+}
+
+impl Lox {
+    pub fn new() -> Self {
+        Lox::with_output(Box::new(io::stdout()))
+    }
+
+    // Sends everything the script prints to `output` instead of stdout.
+    pub fn with_output(output: Box<dyn Write>) -> Self {
+        Lox {
+            interpreter: Interpreter::with_output(output),
+        }
+    }
+
+    // Bounds every later `eval` and `call`. A program that runs into a
+    // limit fails with a `RuntimeErrorKind::LimitExceeded` error.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.interpreter.set_limits(limits);
+    }
+
+    // Runs `source`. If it ends with an expression statement, that
+    // expression's value is returned; otherwise the result is nil.
+    pub fn eval(&mut self, source: &str) -> Result<Value, Error> {
+        let (statements, diagnostics) = backend::parse(source);
+        if !diagnostics.is_empty() {
+            return Err(Error::Compile(diagnostics));
+        }
+
+        let locals = Resolver::new()
+            .resolve(&statements)
+            .map_err(|errors| Error::Compile(errors.iter().map(Diagnostic::from).collect()))?;
+        self.interpreter.resolve(locals);
+
+        match statements.split_last() {
+            Some((ast::Stmt::Expression(last), rest)) => {
+                self.interpreter.interpret(rest)?;
+                Ok(self.interpreter.evaluate(&last.exp)?)
+            }
+            _ => {
+                self.interpreter.interpret(&statements)?;
+                Ok(Value::None)
+            }
+        }
+    }
+
+    pub fn run_file<P: AsRef<Path>>(&mut self, path: P) -> Result<Value, Error> {
+        let source = fs::read_to_string(path)?;
+        self.eval(&source)
+    }
+
+    pub fn get_global(&self, name: &str) -> Option<Value> {
+        self.interpreter.get_global(name)
+    }
+
+    pub fn set_global(&mut self, name: &str, value: impl IntoLox) {
+        self.interpreter.define_global(name, value.into_lox());
+    }
+
+    // Exposes a Rust closure to scripts as a global function. Its parameters
+    // are converted with `FromLox`; mismatches become Lox runtime errors.
+    pub fn register_fn<Args>(&mut self, name: &str, function: impl IntoNative<Args>) {
+        self.register(name, function.into_native());
+    }
+
+    // Exposes a host type to scripts as a global class. Calling the class
+    // runs `constructor`, which returns the new object from `Value::host`.
+    pub fn register_class<Args>(&mut self, name: &str, constructor: impl IntoNative<Args>) {
+        let class = HostClass::new(name, constructor.into_native());
+        self.interpreter
+            .define_global(name, Value::HostClass(Rc::new(class)));
+    }
+
+    pub fn register(&mut self, name: &str, function: Rc<dyn NativeFunction>) {
+        let native = Native::new(name, function);
+        self.interpreter
+            .define_global(name, Value::Native(Rc::new(native)));
+    }
+
+    // Calls a global function or class by name. Errors raised by the host
+    // call itself, such as a wrong argument count, have no source location.
+    pub fn call(&mut self, function_name: &str, args: Vec<Value>) -> Result<Value, Error> {
+        let callee = self.get_global(function_name).ok_or_else(|| {
+            RuntimeError::unlocated(&format!("undefined variable '{}'", function_name))
+        })?;
+        self.interpreter.start_budget();
+        Ok(self.interpreter.call_value(&callee, args, None)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::super::interpreter::OutputBuffer;
+    use super::*;
+
+    #[test]
+    fn eval_returns_the_last_expression() {
+        let mut lox = Lox::with_output(Box::new(OutputBuffer::new()));
+
+        assert_eq!(lox.eval("var a = 2; a * 21;").unwrap(), Value::Num(42.0));
+        assert_eq!(lox.eval("var b = 1;").unwrap(), Value::None);
+        assert_eq!(lox.eval("a + b;").unwrap(), Value::Num(3.0));
+    }
+
+    #[test]
+    fn output_goes_to_the_writer() {
+        let output = OutputBuffer::new();
+        let mut lox = Lox::with_output(Box::new(output.clone()));
+
+        lox.eval("print \"hello\";").unwrap();
+        assert_eq!(output.contents(), "hello\n");
+    }
+
+    #[test]
+    fn globals() {
+        let mut lox = Lox::with_output(Box::new(OutputBuffer::new()));
+        lox.set_global("limit", Value::Num(3.0));
+        lox.eval("var doubled = limit * 2;").unwrap();
+
+        assert_eq!(lox.get_global("doubled"), Some(Value::Num(6.0)));
+        assert_eq!(lox.get_global("missing"), None);
+    }
+
+    #[test]
+    fn call_functions_by_name() {
+        let mut lox = Lox::with_output(Box::new(OutputBuffer::new()));
+        lox.eval("fun greet(name) { return \"hi \" + name; } class Point {}")
+            .unwrap();
+
+        assert_eq!(
+            lox.call("greet", vec![Value::Str("bob".to_owned())])
+                .unwrap(),
+            Value::Str("hi bob".to_owned())
+        );
+        assert_eq!(
+            lox.call("Point", vec![]).unwrap().to_string(),
+            "Point instance"
+        );
+        assert_eq!(
+            lox.call("greet", vec![]).unwrap_err().to_string(),
+            "expected 1 arguments but got 0"
+        );
+        assert_eq!(
+            lox.call("nope", vec![]).unwrap_err().to_string(),
+            "undefined variable 'nope'"
+        );
+    }
+
+    #[test]
+    fn limits_raise_catchable_errors() {
+        let mut lox = Lox::with_output(Box::new(OutputBuffer::new()));
+        lox.set_limits(Limits::unlimited().with_max_steps(1000));
+        lox.eval("fun spin() { while (true) {} }").unwrap();
+
+        assert!(matches!(
+            lox.call("spin", vec![]),
+            Err(Error::Runtime(RuntimeError {
+                kind: RuntimeErrorKind::LimitExceeded(Limit::Steps),
+                ..
+            }))
+        ));
+        assert_eq!(lox.eval("1 + 1;").unwrap(), Value::Num(2.0));
+    }
+
+    #[test]
+    fn errors_are_typed() {
+        let mut lox = Lox::with_output(Box::new(OutputBuffer::new()));
+
+        assert!(matches!(lox.eval("print ;"), Err(Error::Compile(d)) if d.len() == 1));
+        assert!(matches!(lox.eval("return 1;"), Err(Error::Compile(_))));
+        assert!(matches!(
+            lox.eval("print -nil;"),
+            Err(Error::Runtime(RuntimeError { line: Some(1), .. }))
+        ));
+        assert!(matches!(
+            lox.run_file("no/such/file.lox"),
+            Err(Error::Io(_))
+        ));
+    }
+
+    #[test]
+    fn registered_functions() {
+        let output = OutputBuffer::new();
+        let mut lox = Lox::with_output(Box::new(output.clone()));
+        lox.register_fn("add", |a: f64, b: f64| a + b);
+        lox.register_fn("shout", |s: String| s.to_uppercase());
+        lox.register_fn("checked", |n: f64| {
+            if n < 0.0 {
+                Err("negative input")
+            } else {
+                Ok(n)
+            }
+        });
+
+        lox.eval("print add(1, 2); print shout(\"hi\"); print add;")
+            .unwrap();
+        assert_eq!(output.contents(), "3\nHI\n<native fn>\n");
+        assert_eq!(
+            lox.call("add", vec![Value::Num(2.0), Value::Num(2.0)])
+                .unwrap(),
+            Value::Num(4.0)
+        );
+
+        let error = |lox: &mut Lox, source: &str| lox.eval(source).unwrap_err().to_string();
+        assert_eq!(
+            error(&mut lox, "var x = 1;\n\nadd(1);"),
+            "line 3: expected 2 arguments but got 1"
+        );
+        assert_eq!(
+            error(&mut lox, "\nadd(1, \"2\");"),
+            "line 2: expected number for argument 2 of 'add' but got string"
+        );
+        assert_eq!(
+            error(&mut lox, "print 1;\nchecked(-1);"),
+            "line 2: negative input"
+        );
+    }
+
+    #[test]
+    fn natives_as_trait_objects() {
+        struct Counter(std::cell::Cell<f64>);
+
+        impl NativeFunction for Counter {
+            fn arity(&self) -> usize {
+                0
+            }
+
+            fn call(&self, _: &[Value]) -> Result<Value, NativeError> {
+                self.0.set(self.0.get() + 1.0);
+                Ok(Value::Num(self.0.get()))
+            }
+        }
+
+        let mut lox = Lox::with_output(Box::new(OutputBuffer::new()));
+        lox.register("next", Rc::new(Counter(std::cell::Cell::new(0.0))));
+        lox.set_global("name", "lox");
+
+        assert_eq!(lox.eval("next(); next();").unwrap(), Value::Num(2.0));
+        assert_eq!(lox.get_global("name"), Some(Value::Str("lox".to_owned())));
+    }
+}
@@ -0,0 +1,317 @@
+use super::interpreter::Value;
+use std::fmt;
+use std::rc::Rc;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum NativeError {
+    // An argument could not be converted to the type the function takes.
+    Argument {
+        index: usize,
+        expected: &'static str,
+        found: &'static str,
+    },
+    Message(String),
+}
+
+impl NativeError {
+    // Describes the error as raised by the native called `name`.
+    pub fn describe(&self, name: &str) -> String {
+        match self {
+            NativeError::Argument {
+                index,
+                expected,
+                found,
+            } => format!(
+                "expected {} for argument {} of '{}' but got {}",
+                expected,
+                index + 1,
+                name,
+                found
+            ),
+            NativeError::Message(message) => message.clone(),
+        }
+    }
+}
+
+impl fmt::Display for NativeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.describe("<native fn>"))
+    }
+}
+
+impl From<String> for NativeError {
+    fn from(message: String) -> Self {
+        NativeError::Message(message)
+    }
+}
+
+impl From<&str> for NativeError {
+    fn from(message: &str) -> Self {
+        NativeError::Message(message.to_owned())
+    }
+}
+
+// A Rust function callable from Lox. Arity is checked before `call`.
+pub trait NativeFunction {
+    fn arity(&self) -> usize;
+    fn call(&self, arguments: &[Value]) -> Result<Value, NativeError>;
+}
+
+pub trait FromLox: Sized {
+    const TYPE_NAME: &'static str;
+
+    fn from_lox(value: &Value) -> Option<Self>;
+}
+
+pub trait IntoLox {
+    fn into_lox(self) -> Value;
+}
+
+// What a registered closure may return: a plain value, or a `Result` whose
+// error becomes a Lox runtime error.
+pub trait IntoNativeResult {
+    fn into_native_result(self) -> Result<Value, NativeError>;
+}
+
+impl<T: IntoLox> IntoNativeResult for T {
+    fn into_native_result(self) -> Result<Value, NativeError> {
+        Ok(self.into_lox())
+    }
+}
+
+impl<T: IntoLox, E: Into<NativeError>> IntoNativeResult for Result<T, E> {
+    fn into_native_result(self) -> Result<Value, NativeError> {
+        self.map(IntoLox::into_lox).map_err(Into::into)
+    }
+}
+
+// Turns a closure whose parameters implement `FromLox` into a native.
+pub trait IntoNative<Args> {
+    fn into_native(self) -> Rc<dyn NativeFunction>;
+}
+
+struct FnNative<F> {
+    arity: usize,
+    function: F,
+}
+
+impl<F> NativeFunction for FnNative<F>
+where
+    F: Fn(&[Value]) -> Result<Value, NativeError>,
+{
+    fn arity(&self) -> usize {
+        self.arity
+    }
+
+    fn call(&self, arguments: &[Value]) -> Result<Value, NativeError> {
+        (self.function)(arguments)
+    }
+}
+
+fn argument<T: FromLox>(arguments: &[Value], index: usize) -> Result<T, NativeError> {
+    T::from_lox(&arguments[index]).ok_or_else(|| NativeError::Argument {
+        index,
+        expected: T::TYPE_NAME,
+        found: arguments[index].type_name(),
+    })
+}
+
+macro_rules! impl_into_native {
+    ($($arg:ident $index:tt),*) => {
+        impl<F, R, $($arg),*> IntoNative<($($arg,)*)> for F
+        where
+            F: Fn($($arg),*) -> R + 'static,
+            R: IntoNativeResult,
+            $($arg: FromLox,)*
+        {
+            #[allow(unused_variables)]
+            fn into_native(self) -> Rc<dyn NativeFunction> {
+                Rc::new(FnNative {
+                    arity: 0 $(+ { let _ = $index; 1 })*,
+                    function: move |arguments: &[Value]| {
+                        self($(argument::<$arg>(arguments, $index)?),*).into_native_result()
+                    },
+                })
+            }
+        }
+    };
+}
+
+impl_into_native!();
+impl_into_native!(A 0);
+impl_into_native!(A 0, B 1);
+impl_into_native!(A 0, B 1, C 2);
+impl_into_native!(A 0, B 1, C 2, D 3);
+impl_into_native!(A 0, B 1, C 2, D 3, E 4);
+
+// Conversions
+
+impl FromLox for f64 {
+    const TYPE_NAME: &'static str = "number";
+
+    fn from_lox(value: &Value) -> Option<Self> {
+        match value {
+            Value::Num(n) => Some(*n),
+            _ => None,
+        }
+    }
+}
+
+impl FromLox for bool {
+    const TYPE_NAME: &'static str = "boolean";
+
+    fn from_lox(value: &Value) -> Option<Self> {
+        match value {
+            Value::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+}
+
+impl FromLox for String {
+    const TYPE_NAME: &'static str = "string";
+
+    fn from_lox(value: &Value) -> Option<Self> {
+        match value {
+            Value::Str(s) => Some(s.clone()),
+            _ => None,
+        }
+    }
+}
+
+impl FromLox for Value {
+    const TYPE_NAME: &'static str = "value";
+
+    fn from_lox(value: &Value) -> Option<Self> {
+        Some(value.clone())
+    }
+}
+
+impl<T: FromLox> FromLox for Option<T> {
+    const TYPE_NAME: &'static str = T::TYPE_NAME;
+
+    fn from_lox(value: &Value) -> Option<Self> {
+        match value {
+            Value::None => Some(None),
+            _ => T::from_lox(value).map(Some),
+        }
+    }
+}
+
+impl IntoLox for f64 {
+    fn into_lox(self) -> Value {
+        Value::Num(self)
+    }
+}
+
+impl IntoLox for i32 {
+    fn into_lox(self) -> Value {
+        Value::Num(self.into())
+    }
+}
+
+impl IntoLox for bool {
+    fn into_lox(self) -> Value {
+        Value::Bool(self)
+    }
+}
+
+impl IntoLox for String {
+    fn into_lox(self) -> Value {
+        Value::Str(self)
+    }
+}
+
+impl IntoLox for &str {
+    fn into_lox(self) -> Value {
+        Value::Str(self.to_owned())
+    }
+}
+
+impl IntoLox for () {
+    fn into_lox(self) -> Value {
+        Value::None
+    }
+}
+
+impl IntoLox for Value {
+    fn into_lox(self) -> Value {
+        self
+    }
+}
+
+impl<T: IntoLox> IntoLox for Option<T> {
+    fn into_lox(self) -> Value {
+        self.map_or(Value::None, IntoLox::into_lox)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn closures_convert_their_arguments() {
+        let add = (|a: f64, b: f64| a + b).into_native();
+
+        assert_eq!(add.arity(), 2);
+        assert_eq!(
+            add.call(&[Value::Num(1.0), Value::Num(2.0)]),
+            Ok(Value::Num(3.0))
+        );
+        assert_eq!(
+            add.call(&[Value::Num(1.0), Value::Str("2".to_owned())]),
+            Err(NativeError::Argument {
+                index: 1,
+                expected: "number",
+                found: "string",
+            })
+        );
+    }
+
+    #[test]
+    fn optional_arguments_accept_nil() {
+        let greet =
+            (|name: Option<String>| format!("hi {}", name.unwrap_or_default())).into_native();
+
+        assert_eq!(greet.call(&[Value::None]), Ok(Value::Str("hi ".to_owned())));
+        assert_eq!(
+            greet.call(&[Value::Str("bob".to_owned())]),
+            Ok(Value::Str("hi bob".to_owned()))
+        );
+    }
+
+    #[test]
+    fn results_become_errors() {
+        let sqrt = (|n: f64| {
+            if n < 0.0 {
+                Err(format!("can't take the square root of {}", n))
+            } else {
+                Ok(n.sqrt())
+            }
+        })
+        .into_native();
+
+        assert_eq!(sqrt.call(&[Value::Num(9.0)]), Ok(Value::Num(3.0)));
+        assert_eq!(
+            sqrt.call(&[Value::Num(-1.0)])
+                .map_err(|e| e.describe("sqrt")),
+            Err("can't take the square root of -1".to_owned())
+        );
+    }
+
+    #[test]
+    fn argument_errors_name_the_function() {
+        let error = NativeError::Argument {
+            index: 0,
+            expected: "boolean",
+            found: "nil",
+        };
+
+        assert_eq!(
+            error.describe("toggle"),
+            "expected boolean for argument 1 of 'toggle' but got nil"
+        );
+    }
+}
@@ -1,10 +1,54 @@
 use super::ast;
 use super::token;
+use std::fmt;
+use std::sync::Arc;
+
+const MAX_ARGUMENTS: usize = 255;
+
+// Tokens that can begin an expression, reported when one is missing.
+const EXPRESSION_START: [token::Type; 11] = [
+    token::Type::Number,
+    token::Type::String,
+    token::Type::True,
+    token::Type::False,
+    token::Type::Nil,
+    token::Type::This,
+    token::Type::Super,
+    token::Type::Identifier,
+    token::Type::LeftParen,
+    token::Type::Bang,
+    token::Type::Minus,
+];
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ParseErrorKind {
+    MissingToken,
+    ExpectedExpression,
+    InvalidAssignmentTarget,
+    TooManyParameters,
+    TooManyArguments,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    pub token: token::Token,
+    pub span: token::Span,
+    pub expected: Vec<token::Type>,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.span.line, self.message)
+    }
+}
 
 pub struct Parser {
     tokens: Vec<token::Token>,
     cursor: usize,
-    errors: Vec<String>,
+    errors: Vec<ParseError>,
+    panic_mode: bool,
 }
 
 impl Parser {
@@ -14,22 +58,382 @@ impl Parser {
             tokens,
             cursor: 0,
             errors: Vec::with_capacity(5),
+            panic_mode: false,
         }
     }
 
-    pub fn parse(&mut self) -> Result<ast::Expr, String> {
-        let e = self.expression();
+    // Returns every statement that parsed cleanly along with the errors for
+    // the ones that did not, so a broken buffer still yields a partial tree.
+    pub fn parse(&mut self) -> (Vec<ast::Stmt>, Vec<ParseError>) {
+        let mut statements = Vec::new();
 
-        if self.errors.len() == 0 {
-            Ok(e)
-        } else {
-            Err(self.errors.join("\n"))
+        while !self.is_at_end() {
+            if let Some(s) = self.declaration() {
+                statements.push(s);
+            }
         }
+
+        (statements, std::mem::take(&mut self.errors))
     }
 
     // Parse
+    fn declaration(&mut self) -> Option<ast::Stmt> {
+        let start = self.cursor;
+        let s = if self.match_token_type(token::Type::Class) {
+            self.class_declaration()
+        } else if self.match_token_type(token::Type::Fun) {
+            let start = self.previous_span();
+            ast::Stmt::Function(self.function("function", start))
+        } else if self.match_token_type(token::Type::Var) {
+            self.var_declaration()
+        } else {
+            self.statement()
+        };
+
+        if self.panic_mode {
+            if self.cursor == start {
+                self.advance();
+            }
+            self.synchronize();
+            None
+        } else {
+            Some(s)
+        }
+    }
+
+    fn class_declaration(&mut self) -> ast::Stmt {
+        let start = self.previous_span();
+        let name = self.tokens[self.cursor].clone();
+        self.consume(token::Type::Identifier, "expect class name");
+
+        let superclass = if self.match_token_type(token::Type::Less) {
+            let name = self.tokens[self.cursor].clone();
+            self.consume(token::Type::Identifier, "expect superclass name");
+            Some(ast::Variable {
+                id: ast::next_id(),
+                name,
+            })
+        } else {
+            None
+        };
+
+        self.consume(token::Type::LeftBrace, "expect '{' before class body");
+
+        let mut methods = Vec::new();
+        while !self.check(token::Type::RightBrace) && !self.is_at_end() {
+            let start = self.current_span();
+            methods.push(self.function("method", start));
+            if self.panic_mode {
+                break;
+            }
+        }
+
+        self.consume(token::Type::RightBrace, "expect '}' after class body");
+        ast::Stmt::Class(Box::new(ast::Class {
+            name,
+            superclass,
+            methods,
+            span: self.span_from(start),
+        }))
+    }
+
+    fn function(&mut self, kind: &str, start: token::Span) -> Arc<ast::Function> {
+        let name = self.tokens[self.cursor].clone();
+        self.consume(token::Type::Identifier, &format!("expect {} name", kind));
+        self.consume(
+            token::Type::LeftParen,
+            &format!("expect '(' after {} name", kind),
+        );
+
+        let mut params = Vec::new();
+        if !self.check(token::Type::RightParen) {
+            loop {
+                if params.len() >= MAX_ARGUMENTS {
+                    let current = self.tokens[self.cursor].clone();
+                    self.error_at(
+                        &current,
+                        ParseErrorKind::TooManyParameters,
+                        &[],
+                        "can't have more than 255 parameters",
+                    );
+                }
+                params.push(self.tokens[self.cursor].clone());
+                self.consume(token::Type::Identifier, "expect parameter name");
+
+                if !self.match_token_type(token::Type::Comma) {
+                    break;
+                }
+            }
+        }
+        self.consume(token::Type::RightParen, "expect ')' after parameters");
+
+        self.consume(
+            token::Type::LeftBrace,
+            &format!("expect '{{' before {} body", kind),
+        );
+        let body = self.block();
+
+        Arc::new(ast::Function {
+            name,
+            params,
+            body,
+            span: self.span_from(start),
+        })
+    }
+
+    fn var_declaration(&mut self) -> ast::Stmt {
+        let start = self.previous_span();
+        let name = self.tokens[self.cursor].clone();
+        self.consume(token::Type::Identifier, "expect variable name");
+
+        let initializer = if self.match_token_type(token::Type::Equal) {
+            Some(self.expression())
+        } else {
+            None
+        };
+
+        self.consume(
+            token::Type::Semicolon,
+            "expect ';' after variable declaration",
+        );
+        ast::Stmt::Var(Box::new(ast::Var {
+            name,
+            initializer,
+            span: self.span_from(start),
+        }))
+    }
+
+    fn statement(&mut self) -> ast::Stmt {
+        if self.match_token_type(token::Type::For) {
+            self.for_statement()
+        } else if self.match_token_type(token::Type::If) {
+            self.if_statement()
+        } else if self.match_token_type(token::Type::Print) {
+            self.print_statement()
+        } else if self.match_token_type(token::Type::Return) {
+            self.return_statement()
+        } else if self.match_token_type(token::Type::While) {
+            self.while_statement()
+        } else if self.match_token_type(token::Type::LeftBrace) {
+            let start = self.previous_span();
+            let statements = self.block();
+            ast::Stmt::Block(Box::new(ast::Block {
+                statements,
+                span: self.span_from(start),
+            }))
+        } else {
+            self.expression_statement()
+        }
+    }
+
+    // Desugars into a while loop. The nodes made up along the way all span
+    // the whole `for` statement.
+    fn for_statement(&mut self) -> ast::Stmt {
+        let start = self.previous_span();
+        self.consume(token::Type::LeftParen, "expect '(' after 'for'");
+
+        let initializer = if self.match_token_type(token::Type::Semicolon) {
+            None
+        } else if self.match_token_type(token::Type::Var) {
+            Some(self.var_declaration())
+        } else {
+            Some(self.expression_statement())
+        };
+
+        let condition = if !self.check(token::Type::Semicolon) {
+            self.expression()
+        } else {
+            let semicolon = self.current_span();
+            ast::Expr::Literal(Box::new(ast::Literal {
+                value: token::Value::Bool(true),
+                span: token::Span {
+                    end: semicolon.start,
+                    ..semicolon
+                },
+            }))
+        };
+        self.consume(token::Type::Semicolon, "expect ';' after loop condition");
+
+        let increment = if !self.check(token::Type::RightParen) {
+            Some(self.expression())
+        } else {
+            None
+        };
+        self.consume(token::Type::RightParen, "expect ')' after for clauses");
+
+        let mut body = self.statement();
+        let span = self.span_from(start);
+
+        if let Some(exp) = increment {
+            let increment = ast::Expression {
+                span: exp.span().unwrap_or(span),
+                exp,
+            };
+            body = ast::Stmt::Block(Box::new(ast::Block {
+                statements: vec![body, ast::Stmt::Expression(Box::new(increment))],
+                span,
+            }));
+        }
+
+        body = ast::Stmt::While(Box::new(ast::While {
+            condition,
+            body,
+            span,
+        }));
+
+        if let Some(initializer) = initializer {
+            body = ast::Stmt::Block(Box::new(ast::Block {
+                statements: vec![initializer, body],
+                span,
+            }));
+        }
+
+        body
+    }
+
+    fn if_statement(&mut self) -> ast::Stmt {
+        let start = self.previous_span();
+        self.consume(token::Type::LeftParen, "expect '(' after 'if'");
+        let condition = self.expression();
+        self.consume(token::Type::RightParen, "expect ')' after if condition");
+
+        let then_branch = self.statement();
+        let else_branch = if self.match_token_type(token::Type::Else) {
+            Some(self.statement())
+        } else {
+            None
+        };
+
+        ast::Stmt::If(Box::new(ast::If {
+            condition,
+            then_branch,
+            else_branch,
+            span: self.span_from(start),
+        }))
+    }
+
+    fn while_statement(&mut self) -> ast::Stmt {
+        let start = self.previous_span();
+        self.consume(token::Type::LeftParen, "expect '(' after 'while'");
+        let condition = self.expression();
+        self.consume(token::Type::RightParen, "expect ')' after condition");
+        let body = self.statement();
+
+        ast::Stmt::While(Box::new(ast::While {
+            condition,
+            body,
+            span: self.span_from(start),
+        }))
+    }
+
+    fn print_statement(&mut self) -> ast::Stmt {
+        let keyword = self.previous();
+        let exp = self.expression();
+        self.consume(token::Type::Semicolon, "expect ';' after value");
+        let span = self.span_from(keyword.span);
+        ast::Stmt::Print(Box::new(ast::Print { keyword, exp, span }))
+    }
+
+    fn return_statement(&mut self) -> ast::Stmt {
+        let keyword = self.previous();
+        let value = if !self.check(token::Type::Semicolon) {
+            Some(self.expression())
+        } else {
+            None
+        };
+
+        self.consume(token::Type::Semicolon, "expect ';' after return value");
+        let span = self.span_from(keyword.span);
+        ast::Stmt::Return(Box::new(ast::Return {
+            keyword,
+            value,
+            span,
+        }))
+    }
+
+    fn expression_statement(&mut self) -> ast::Stmt {
+        let start = self.current_span();
+        let exp = self.expression();
+        self.consume(token::Type::Semicolon, "expect ';' after expression");
+        ast::Stmt::Expression(Box::new(ast::Expression {
+            exp,
+            span: self.span_from(start),
+        }))
+    }
+
+    fn block(&mut self) -> Vec<ast::Stmt> {
+        let mut statements = Vec::new();
+
+        while !self.check(token::Type::RightBrace) && !self.is_at_end() {
+            if let Some(s) = self.declaration() {
+                statements.push(s);
+            }
+        }
+
+        self.consume(token::Type::RightBrace, "expect '}' after block");
+        statements
+    }
+
     fn expression(&mut self) -> ast::Expr {
-        self.equality()
+        self.assignment()
+    }
+
+    fn assignment(&mut self) -> ast::Expr {
+        let e = self.or();
+
+        if self.match_token_type(token::Type::Equal) {
+            let equals = self.previous();
+            let value = self.assignment();
+
+            match e {
+                ast::Expr::Variable(v) => {
+                    return ast::Expr::Assign(Box::new(ast::Assign {
+                        id: ast::next_id(),
+                        name: v.name,
+                        value,
+                    }))
+                }
+                ast::Expr::Get(g) => {
+                    return ast::Expr::Set(Box::new(ast::Set {
+                        object: g.object,
+                        name: g.name,
+                        value,
+                    }))
+                }
+                _ => self.error_at(
+                    &equals,
+                    ParseErrorKind::InvalidAssignmentTarget,
+                    &[],
+                    "invalid assignment target",
+                ),
+            }
+        }
+
+        e
+    }
+
+    fn or(&mut self) -> ast::Expr {
+        let mut e = self.and();
+
+        while self.match_token_type(token::Type::Or) {
+            let op = self.previous();
+            let right = self.and();
+            e = ast::Expr::Logical(Box::new(ast::Logical { left: e, op, right }))
+        }
+
+        e
+    }
+
+    fn and(&mut self) -> ast::Expr {
+        let mut e = self.equality();
+
+        while self.match_token_type(token::Type::And) {
+            let op = self.previous();
+            let right = self.equality();
+            e = ast::Expr::Logical(Box::new(ast::Logical { left: e, op, right }))
+        }
+
+        e
     }
 
     fn equality(&mut self) -> ast::Expr {
@@ -86,28 +490,105 @@ impl Parser {
             let right = self.unary();
             ast::Expr::Unary(Box::new(ast::Unary { op, right }))
         } else {
-            self.primary()
+            self.call()
         }
     }
 
+    fn call(&mut self) -> ast::Expr {
+        let mut e = self.primary();
+
+        loop {
+            if self.match_token_type(token::Type::LeftParen) {
+                e = self.finish_call(e);
+            } else if self.match_token_type(token::Type::Dot) {
+                let name = self.tokens[self.cursor].clone();
+                if !self.consume(token::Type::Identifier, "expect property name after '.'") {
+                    break;
+                }
+                e = ast::Expr::Get(Box::new(ast::Get { object: e, name }));
+            } else {
+                break;
+            }
+        }
+
+        e
+    }
+
+    fn finish_call(&mut self, callee: ast::Expr) -> ast::Expr {
+        let mut arguments = Vec::new();
+
+        if !self.check(token::Type::RightParen) {
+            loop {
+                if arguments.len() >= MAX_ARGUMENTS {
+                    let current = self.tokens[self.cursor].clone();
+                    self.error_at(
+                        &current,
+                        ParseErrorKind::TooManyArguments,
+                        &[],
+                        "can't have more than 255 arguments",
+                    );
+                }
+                arguments.push(self.expression());
+
+                if !self.match_token_type(token::Type::Comma) {
+                    break;
+                }
+            }
+        }
+
+        let paren = self.tokens[self.cursor].clone();
+        self.consume(token::Type::RightParen, "expect ')' after arguments");
+
+        ast::Expr::Call(Box::new(ast::Call {
+            callee,
+            paren,
+            arguments,
+        }))
+    }
+
     fn primary(&mut self) -> ast::Expr {
         if self.match_token_types_literal() {
             ast::Expr::Literal(Box::new(ast::Literal {
                 value: self.previous_value(),
+                span: self.previous_span(),
+            }))
+        } else if self.match_token_type(token::Type::Super) {
+            let keyword = self.previous();
+            self.consume(token::Type::Dot, "expect '.' after 'super'");
+            let method = self.tokens[self.cursor].clone();
+            self.consume(token::Type::Identifier, "expect superclass method name");
+            ast::Expr::Super(Box::new(ast::Super {
+                id: ast::next_id(),
+                keyword,
+                method,
+            }))
+        } else if self.match_token_type(token::Type::This) {
+            ast::Expr::This(Box::new(ast::This {
+                id: ast::next_id(),
+                keyword: self.previous(),
+            }))
+        } else if self.match_token_type(token::Type::Identifier) {
+            ast::Expr::Variable(Box::new(ast::Variable {
+                id: ast::next_id(),
+                name: self.previous(),
             }))
         } else if self.match_token_types_left_paren() {
+            let start = self.previous_span();
             let exp = self.expression();
             if !self.consume(token::Type::RightParen, "expecting ')'") {
-                self.synchronize();
                 ast::Expr::None
             } else {
-                ast::Expr::Grouping(Box::new(ast::Grouping { exp }))
+                ast::Expr::Grouping(Box::new(ast::Grouping {
+                    exp,
+                    span: self.span_from(start),
+                }))
             }
         } else {
-            self.errors.push(std::fmt::format(format_args!(
-                "line {}: {}",
-                self.tokens[self.cursor].line, "expect expression"
-            )));
+            self.error(
+                ParseErrorKind::ExpectedExpression,
+                &EXPRESSION_START,
+                "expect expression",
+            );
             ast::Expr::None
         }
     }
@@ -116,7 +597,7 @@ impl Parser {
 
     fn match_token_types_equality(&mut self) -> bool {
         let t = self.tokens[self.cursor].typ;
-        let m = t == token::Type::Equal || t == token::Type::BangEqual;
+        let m = t == token::Type::EqualEqual || t == token::Type::BangEqual;
         if m {
             self.advance();
         }
@@ -125,8 +606,7 @@ impl Parser {
 
     fn match_token_types_comparison(&mut self) -> bool {
         let t = self.tokens[self.cursor].typ;
-        let m = t == token::Type::BangEqual
-            || t == token::Type::Greater
+        let m = t == token::Type::Greater
             || t == token::Type::GreaterEqual
             || t == token::Type::Less
             || t == token::Type::LessEqual;
@@ -184,23 +664,54 @@ impl Parser {
         m
     }
 
-    fn consume(&mut self, token_type: token::Type, msg: &str) -> bool {
-        let current_token = &self.tokens[self.cursor];
+    fn match_token_type(&mut self, token_type: token::Type) -> bool {
+        let m = self.check(token_type);
+        if m {
+            self.advance();
+        }
+        m
+    }
+
+    fn check(&self, token_type: token::Type) -> bool {
+        self.tokens[self.cursor].typ == token_type
+    }
 
-        if current_token.typ == token_type {
+    fn consume(&mut self, token_type: token::Type, msg: &str) -> bool {
+        if self.check(token_type) {
             self.advance();
             true
         } else {
-            self.errors.push(std::fmt::format(format_args!(
-                "line {}: {}",
-                current_token.line, msg
-            )));
+            self.error(ParseErrorKind::MissingToken, &[token_type], msg);
             false
         }
     }
 
+    fn error(&mut self, kind: ParseErrorKind, expected: &[token::Type], msg: &str) {
+        if self.panic_mode {
+            return;
+        }
+        self.panic_mode = true;
+        self.error_at(&self.tokens[self.cursor].clone(), kind, expected, msg);
+    }
+
+    fn error_at(
+        &mut self,
+        token: &token::Token,
+        kind: ParseErrorKind,
+        expected: &[token::Type],
+        msg: &str,
+    ) {
+        self.errors.push(ParseError {
+            kind,
+            token: token.clone(),
+            span: token.span,
+            expected: expected.to_vec(),
+            message: msg.to_owned(),
+        });
+    }
+
     fn synchronize(&mut self) {
-        self.advance();
+        self.panic_mode = false;
 
         while !self.is_at_end() {
             let previous_type = self.previous().typ;
@@ -240,4 +751,339 @@ impl Parser {
     fn previous_value(&self) -> token::Value {
         self.tokens[self.cursor - 1].literal.clone()
     }
+
+    fn previous_span(&self) -> token::Span {
+        self.tokens[self.cursor - 1].span
+    }
+
+    fn current_span(&self) -> token::Span {
+        self.tokens[self.cursor].span
+    }
+
+    // From `start` to the end of the last token consumed.
+    fn span_from(&self, start: token::Span) -> token::Span {
+        start.to(self.previous_span())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::super::scanner;
+    use super::*;
+
+    fn parse(source: &str) -> Result<Vec<ast::Stmt>, String> {
+        let tokens = scanner::Scanner::new(source).parse();
+        let (statements, errors) = Parser::new(tokens).parse();
+
+        if errors.is_empty() {
+            Ok(statements)
+        } else {
+            let lines: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+            Err(lines.join("\n"))
+        }
+    }
+
+    #[test]
+    fn statements() {
+        let statements = parse("var a = 1; var b; print a; { a = b; }").unwrap();
+
+        assert_eq!(statements.len(), 4);
+        assert!(matches!(&statements[0], ast::Stmt::Var(v) if v.initializer.is_some()));
+        assert!(matches!(&statements[1], ast::Stmt::Var(v) if v.initializer.is_none()));
+        assert!(matches!(statements[2], ast::Stmt::Print(_)));
+        if let ast::Stmt::Block(b) = &statements[3] {
+            assert_eq!(b.statements.len(), 1);
+            assert!(matches!(b.statements[0], ast::Stmt::Expression(_)));
+        } else {
+            panic!("expected a block");
+        }
+    }
+
+    #[test]
+    fn recovers_after_each_statement() {
+        assert_eq!(
+            parse("print 1 +;\nprint 2;\nvar = 3;\nprint (4;\nprint 5"),
+            Err([
+                "line 1: expect expression",
+                "line 3: expect variable name",
+                "line 4: expecting ')'",
+                "line 5: expect ';' after value",
+            ]
+            .join("\n"))
+        );
+    }
+
+    #[test]
+    fn assignment() {
+        let statements = parse("a = b = 1;").unwrap();
+
+        if let ast::Stmt::Expression(e) = &statements[0] {
+            if let ast::Expr::Assign(a) = &e.exp {
+                assert_eq!(a.name.name(), "a");
+                assert!(matches!(&a.value, ast::Expr::Assign(b) if b.name.name() == "b"));
+            } else {
+                panic!("expected an assignment");
+            }
+        } else {
+            panic!("expected an expression statement");
+        }
+    }
+
+    #[test]
+    fn invalid_assignment_target() {
+        assert_eq!(
+            parse("var a;\n(a) = 1;\n1 + a = 2;"),
+            Err("line 2: invalid assignment target\nline 3: invalid assignment target".to_owned())
+        );
+    }
+
+    #[test]
+    fn dangling_else_binds_to_nearest_if() {
+        let statements = parse("if (a) if (b) print 1; else print 2;").unwrap();
+
+        assert_eq!(statements.len(), 1);
+        if let ast::Stmt::If(outer) = &statements[0] {
+            assert!(outer.else_branch.is_none());
+            if let ast::Stmt::If(inner) = &outer.then_branch {
+                assert!(matches!(inner.then_branch, ast::Stmt::Print(_)));
+                assert!(matches!(inner.else_branch, Some(ast::Stmt::Print(_))));
+            } else {
+                panic!("expected a nested if");
+            }
+        } else {
+            panic!("expected an if");
+        }
+    }
+
+    #[test]
+    fn dangling_else_with_braces_binds_to_outer_if() {
+        let statements = parse("if (a) { if (b) print 1; } else print 2;").unwrap();
+
+        if let ast::Stmt::If(outer) = &statements[0] {
+            assert!(matches!(outer.then_branch, ast::Stmt::Block(_)));
+            assert!(matches!(outer.else_branch, Some(ast::Stmt::Print(_))));
+        } else {
+            panic!("expected an if");
+        }
+    }
+
+    #[test]
+    fn logical_precedence() {
+        let statements = parse("a or b and c;").unwrap();
+
+        if let ast::Stmt::Expression(e) = &statements[0] {
+            if let ast::Expr::Logical(or) = &e.exp {
+                assert_eq!(or.op.typ, token::Type::Or);
+                assert!(
+                    matches!(&or.right, ast::Expr::Logical(and) if and.op.typ == token::Type::And)
+                );
+            } else {
+                panic!("expected a logical expression");
+            }
+        } else {
+            panic!("expected an expression statement");
+        }
+    }
+
+    #[test]
+    fn for_desugars_to_while() {
+        let statements = parse("for (var i = 0; i < 3; i = i + 1) print i;").unwrap();
+
+        if let ast::Stmt::Block(b) = &statements[0] {
+            assert!(matches!(b.statements[0], ast::Stmt::Var(_)));
+            if let ast::Stmt::While(w) = &b.statements[1] {
+                if let ast::Stmt::Block(body) = &w.body {
+                    assert!(matches!(body.statements[0], ast::Stmt::Print(_)));
+                    assert!(matches!(body.statements[1], ast::Stmt::Expression(_)));
+                } else {
+                    panic!("expected the body and increment in a block");
+                }
+            } else {
+                panic!("expected a while loop");
+            }
+        } else {
+            panic!("expected a block");
+        }
+
+        let statements = parse("for (;;) print 1;").unwrap();
+        if let ast::Stmt::While(w) = &statements[0] {
+            assert!(
+                matches!(&w.condition, ast::Expr::Literal(l) if l.value == token::Value::Bool(true))
+            );
+            assert!(matches!(w.body, ast::Stmt::Print(_)));
+        } else {
+            panic!("expected a while loop");
+        }
+    }
+
+    #[test]
+    fn functions_and_calls() {
+        let statements = parse("fun add(a, b) { return a + b; } print add(1, 2)(3);").unwrap();
+
+        if let ast::Stmt::Function(f) = &statements[0] {
+            assert_eq!(f.name.name(), "add");
+            assert_eq!(
+                vec!["a", "b"],
+                f.params.iter().map(|p| p.name()).collect::<Vec<&str>>()
+            );
+            assert!(matches!(&f.body[0], ast::Stmt::Return(r) if r.value.is_some()));
+        } else {
+            panic!("expected a function");
+        }
+
+        if let ast::Stmt::Print(p) = &statements[1] {
+            if let ast::Expr::Call(outer) = &p.exp {
+                assert_eq!(outer.arguments.len(), 1);
+                assert!(
+                    matches!(&outer.callee, ast::Expr::Call(inner) if inner.arguments.len() == 2)
+                );
+            } else {
+                panic!("expected a call");
+            }
+        } else {
+            panic!("expected a print statement");
+        }
+    }
+
+    #[test]
+    fn too_many_arguments() {
+        let arguments = vec!["1"; 256].join(", ");
+        assert_eq!(
+            parse(&format!("f({});", arguments)),
+            Err("line 1: can't have more than 255 arguments".to_owned())
+        );
+    }
+
+    #[test]
+    fn classes_and_properties() {
+        let statements =
+            parse("class A { init(x) { this.x = x; } get() { return this.x; } } A(1).get().y = 2;")
+                .unwrap();
+
+        if let ast::Stmt::Class(c) = &statements[0] {
+            assert_eq!(c.name.name(), "A");
+            assert_eq!(
+                vec!["init", "get"],
+                c.methods
+                    .iter()
+                    .map(|m| m.name.name())
+                    .collect::<Vec<&str>>()
+            );
+            assert!(
+                matches!(&c.methods[0].body[0], ast::Stmt::Expression(e) if matches!(&e.exp, ast::Expr::Set(s) if matches!(s.object, ast::Expr::This(_))))
+            );
+        } else {
+            panic!("expected a class");
+        }
+
+        if let ast::Stmt::Expression(e) = &statements[1] {
+            if let ast::Expr::Set(s) = &e.exp {
+                assert_eq!(s.name.name(), "y");
+                assert!(
+                    matches!(&s.object, ast::Expr::Call(c) if matches!(&c.callee, ast::Expr::Get(g) if g.name.name() == "get"))
+                );
+            } else {
+                panic!("expected a set expression");
+            }
+        } else {
+            panic!("expected an expression statement");
+        }
+    }
+
+    #[test]
+    fn inheritance_and_super() {
+        let statements = parse("class B < A { m() { return super.m(); } }").unwrap();
+
+        if let ast::Stmt::Class(c) = &statements[0] {
+            assert!(matches!(&c.superclass, Some(v) if v.name.name() == "A"));
+            let ast::Stmt::Return(r) = &c.methods[0].body[0] else {
+                panic!("expected a return statement");
+            };
+            assert!(
+                matches!(&r.value, Some(ast::Expr::Call(c)) if matches!(&c.callee, ast::Expr::Super(s) if s.method.name() == "m"))
+            );
+        } else {
+            panic!("expected a class");
+        }
+
+        assert_eq!(
+            parse("class B < A { m() { super; } }"),
+            Err("line 1: expect '.' after 'super'".to_owned())
+        );
+    }
+
+    #[test]
+    fn unterminated_block() {
+        assert_eq!(
+            parse("{ print 1;"),
+            Err("line 1: expect '}' after block".to_owned())
+        );
+    }
+
+    #[test]
+    fn errors_carry_token_and_expected_types() {
+        let tokens = scanner::Scanner::new("var a = 1\nprint a;").parse();
+        let (_, errors) = Parser::new(tokens).parse();
+
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].kind, ParseErrorKind::MissingToken);
+        assert_eq!(errors[0].token.typ, token::Type::Print);
+        assert_eq!(errors[0].span.line, 2);
+        assert_eq!(errors[0].span.column, 1);
+        assert_eq!(errors[0].expected, vec![token::Type::Semicolon]);
+    }
+
+    #[test]
+    fn missing_expression_lists_expression_starts() {
+        let tokens = scanner::Scanner::new("print ;").parse();
+        let (_, errors) = Parser::new(tokens).parse();
+
+        assert_eq!(errors[0].kind, ParseErrorKind::ExpectedExpression);
+        assert_eq!(errors[0].token.lexeme, ";");
+        assert!(errors[0].expected.contains(&token::Type::Identifier));
+        assert!(errors[0].expected.contains(&token::Type::LeftParen));
+    }
+
+    #[test]
+    fn partial_tree_is_returned_with_errors() {
+        let tokens = scanner::Scanner::new("var a = 1;\nprint +;\nprint a;\n1 = 2;").parse();
+        let (statements, errors) = Parser::new(tokens).parse();
+
+        assert_eq!(
+            errors
+                .iter()
+                .map(|e| e.kind)
+                .collect::<Vec<ParseErrorKind>>(),
+            vec![
+                ParseErrorKind::ExpectedExpression,
+                ParseErrorKind::InvalidAssignmentTarget
+            ]
+        );
+        assert_eq!(statements.len(), 3);
+        assert!(matches!(statements[0], ast::Stmt::Var(_)));
+        assert!(matches!(statements[1], ast::Stmt::Print(_)));
+        assert!(matches!(statements[2], ast::Stmt::Expression(_)));
+    }
+
+    #[test]
+    fn nodes_carry_their_spans() {
+        let source = "print (1 + 2) * -x;\nfor (var i = 0; i < 3;) f(i);";
+        let statements = parse(source).unwrap();
+        let text = |span: token::Span| &source[span.start..span.end];
+
+        let ast::Stmt::Print(p) = &statements[0] else {
+            panic!("expected a print statement");
+        };
+        assert_eq!(text(p.span), "print (1 + 2) * -x;");
+        assert_eq!(text(p.exp.span().unwrap()), "(1 + 2) * -x");
+        let ast::Expr::Binary(b) = &p.exp else {
+            panic!("expected a binary expression");
+        };
+        assert_eq!(text(b.left.span().unwrap()), "(1 + 2)");
+        assert_eq!(text(b.right.span().unwrap()), "-x");
+
+        assert_eq!(text(statements[1].span()), "for (var i = 0; i < 3;) f(i);");
+        assert_eq!(statements[1].span().line, 2);
+    }
 }
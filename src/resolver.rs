@@ -0,0 +1,466 @@
+use super::ast;
+use super::diagnostics::Label;
+use super::token;
+use std::collections::HashMap;
+use std::fmt;
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct ResolveError {
+    pub span: token::Span,
+    pub message: String,
+    // Other code the error involves, such as an earlier declaration.
+    pub secondary: Vec<Label>,
+    pub notes: Vec<String>,
+}
+
+impl ResolveError {
+    fn new(token: &token::Token, message: &str) -> Self {
+        ResolveError {
+            span: token.span,
+            message: message.to_owned(),
+            secondary: Vec::new(),
+            notes: Vec::new(),
+        }
+    }
+}
+
+impl fmt::Display for ResolveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.span.line, self.message)
+    }
+}
+
+// A name declared in a scope. `this` and `super` have no span because the
+// resolver declares them itself.
+struct Declaration {
+    defined: bool,
+    span: Option<token::Span>,
+}
+
+#[derive(PartialEq, Clone, Copy)]
+enum FunctionType {
+    None,
+    Function,
+    Initializer,
+    Method,
+}
+
+#[derive(PartialEq, Clone, Copy)]
+enum ClassType {
+    None,
+    Class,
+    Subclass,
+}
+
+pub struct Resolver {
+    scopes: Vec<HashMap<String, Declaration>>,
+    locals: HashMap<usize, usize>,
+    current_function: FunctionType,
+    current_class: ClassType,
+    errors: Vec<ResolveError>,
+}
+
+impl Default for Resolver {
+    fn default() -> Self {
+        Resolver::new()
+    }
+}
+
+impl Resolver {
+    // Public
+    pub fn new() -> Self {
+        Resolver {
+            scopes: Vec::new(),
+            locals: HashMap::new(),
+            current_function: FunctionType::None,
+            current_class: ClassType::None,
+            errors: Vec::new(),
+        }
+    }
+
+    // Returns the scope distance of every local variable use, keyed by the
+    // id of the expression. Uses that are not found are globals.
+    pub fn resolve(
+        mut self,
+        statements: &[ast::Stmt],
+    ) -> Result<HashMap<usize, usize>, Vec<ResolveError>> {
+        self.resolve_statements(statements);
+
+        if self.errors.is_empty() {
+            Ok(self.locals)
+        } else {
+            Err(self.errors)
+        }
+    }
+
+    // Statements
+    fn resolve_statements(&mut self, statements: &[ast::Stmt]) {
+        for s in statements {
+            self.resolve_statement(s);
+        }
+    }
+
+    fn resolve_statement(&mut self, s: &ast::Stmt) {
+        match s {
+            ast::Stmt::Block(b) => {
+                self.begin_scope();
+                self.resolve_statements(&b.statements);
+                self.end_scope();
+            }
+            ast::Stmt::Class(c) => {
+                let enclosing_class = self.current_class;
+                self.current_class = ClassType::Class;
+
+                self.declare(&c.name);
+                self.define(&c.name);
+
+                if let Some(superclass) = &c.superclass {
+                    if superclass.name.name() == c.name.name() {
+                        self.error(&superclass.name, "a class can't inherit from itself");
+                    }
+                    self.current_class = ClassType::Subclass;
+                    self.resolve_local(superclass.id, &superclass.name);
+
+                    self.begin_scope();
+                    self.implicit("super");
+                }
+
+                self.begin_scope();
+                self.implicit("this");
+                for method in &c.methods {
+                    let typ = if method.name.name() == "init" {
+                        FunctionType::Initializer
+                    } else {
+                        FunctionType::Method
+                    };
+                    self.resolve_function(method, typ);
+                }
+                self.end_scope();
+
+                if c.superclass.is_some() {
+                    self.end_scope();
+                }
+
+                self.current_class = enclosing_class;
+            }
+            ast::Stmt::Expression(e) => self.resolve_expression(&e.exp),
+            ast::Stmt::Function(f) => {
+                self.declare(&f.name);
+                self.define(&f.name);
+                self.resolve_function(f, FunctionType::Function);
+            }
+            ast::Stmt::If(i) => {
+                self.resolve_expression(&i.condition);
+                self.resolve_statement(&i.then_branch);
+                if let Some(else_branch) = &i.else_branch {
+                    self.resolve_statement(else_branch);
+                }
+            }
+            ast::Stmt::Print(p) => self.resolve_expression(&p.exp),
+            ast::Stmt::Return(r) => {
+                if self.current_function == FunctionType::None {
+                    self.error(&r.keyword, "can't return from top-level code");
+                }
+                if let Some(value) = &r.value {
+                    if self.current_function == FunctionType::Initializer {
+                        self.error(&r.keyword, "can't return a value from an initializer");
+                    }
+                    self.resolve_expression(value);
+                }
+            }
+            ast::Stmt::Var(v) => {
+                self.declare(&v.name);
+                if let Some(initializer) = &v.initializer {
+                    self.resolve_expression(initializer);
+                }
+                self.define(&v.name);
+            }
+            ast::Stmt::While(w) => {
+                self.resolve_expression(&w.condition);
+                self.resolve_statement(&w.body);
+            }
+        }
+    }
+
+    fn resolve_function(&mut self, f: &ast::Function, typ: FunctionType) {
+        let enclosing_function = self.current_function;
+        self.current_function = typ;
+
+        self.begin_scope();
+        for param in &f.params {
+            self.declare(param);
+            self.define(param);
+        }
+        self.resolve_statements(&f.body);
+        self.end_scope();
+
+        self.current_function = enclosing_function;
+    }
+
+    // Expressions
+    fn resolve_expression(&mut self, e: &ast::Expr) {
+        match e {
+            ast::Expr::Assign(a) => {
+                self.resolve_expression(&a.value);
+                self.resolve_local(a.id, &a.name);
+            }
+            ast::Expr::Binary(b) => {
+                self.resolve_expression(&b.left);
+                self.resolve_expression(&b.right);
+            }
+            ast::Expr::Call(c) => {
+                self.resolve_expression(&c.callee);
+                for argument in &c.arguments {
+                    self.resolve_expression(argument);
+                }
+            }
+            ast::Expr::Get(g) => self.resolve_expression(&g.object),
+            ast::Expr::Grouping(g) => self.resolve_expression(&g.exp),
+            ast::Expr::Literal(_) => (),
+            ast::Expr::Logical(l) => {
+                self.resolve_expression(&l.left);
+                self.resolve_expression(&l.right);
+            }
+            ast::Expr::Set(s) => {
+                self.resolve_expression(&s.value);
+                self.resolve_expression(&s.object);
+            }
+            ast::Expr::Super(s) => {
+                match self.current_class {
+                    ClassType::None => {
+                        self.error(&s.keyword, "can't use 'super' outside of a class")
+                    }
+                    ClassType::Class => self.error(
+                        &s.keyword,
+                        "can't use 'super' in a class with no superclass",
+                    ),
+                    ClassType::Subclass => (),
+                }
+                self.resolve_local(s.id, &s.keyword);
+            }
+            ast::Expr::This(t) => {
+                if self.current_class == ClassType::None {
+                    self.error(&t.keyword, "can't use 'this' outside of a class");
+                }
+                self.resolve_local(t.id, &t.keyword);
+            }
+            ast::Expr::Unary(u) => self.resolve_expression(&u.right),
+            ast::Expr::Variable(v) => {
+                if let Some(scope) = self.scopes.last() {
+                    if scope.get(v.name.name()).is_some_and(|d| !d.defined) {
+                        self.error(&v.name, "can't read local variable in its own initializer");
+                    }
+                }
+                self.resolve_local(v.id, &v.name);
+            }
+            ast::Expr::None => (),
+        }
+    }
+
+    // Utilities
+
+    fn begin_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    fn end_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    fn declare(&mut self, name: &token::Token) {
+        let Some(scope) = self.scopes.last_mut() else {
+            return;
+        };
+        match scope.get(name.name()) {
+            Some(previous) => {
+                let mut error =
+                    ResolveError::new(name, "already a variable with this name in this scope");
+                if let Some(span) = previous.span {
+                    error.secondary.push(Label {
+                        span,
+                        message: "first declared here".to_owned(),
+                    });
+                }
+                error
+                    .notes
+                    .push("shadowing is only allowed in a nested scope".to_owned());
+                self.errors.push(error);
+            }
+            None => {
+                let declaration = Declaration {
+                    defined: false,
+                    span: Some(name.span),
+                };
+                scope.insert(name.name().to_owned(), declaration);
+            }
+        }
+    }
+
+    fn define(&mut self, name: &token::Token) {
+        if let Some(declaration) = self
+            .scopes
+            .last_mut()
+            .and_then(|scope| scope.get_mut(name.name()))
+        {
+            declaration.defined = true;
+        }
+    }
+
+    fn implicit(&mut self, name: &str) {
+        if let Some(scope) = self.scopes.last_mut() {
+            let declaration = Declaration {
+                defined: true,
+                span: None,
+            };
+            scope.insert(name.to_owned(), declaration);
+        }
+    }
+
+    fn resolve_local(&mut self, id: usize, name: &token::Token) {
+        for (distance, scope) in self.scopes.iter().rev().enumerate() {
+            if scope.contains_key(name.name()) {
+                self.locals.insert(id, distance);
+                return;
+            }
+        }
+    }
+
+    fn error(&mut self, token: &token::Token, message: &str) {
+        self.errors.push(ResolveError::new(token, message));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::super::parser;
+    use super::super::scanner;
+    use super::*;
+
+    fn resolve(source: &str) -> Result<HashMap<usize, usize>, Vec<ResolveError>> {
+        let tokens = scanner::Scanner::new(source).parse();
+        let (statements, errors) = parser::Parser::new(tokens).parse();
+        assert!(errors.is_empty(), "{:?}", errors);
+        Resolver::new().resolve(&statements)
+    }
+
+    fn messages(source: &str) -> Vec<String> {
+        resolve(source)
+            .unwrap_err()
+            .iter()
+            .map(|e| e.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn scope_distances() {
+        let tokens = scanner::Scanner::new("var g; { var a; { a; g; } a = 1; }").parse();
+        let (statements, errors) = parser::Parser::new(tokens).parse();
+        assert!(errors.is_empty(), "{:?}", errors);
+        let locals = Resolver::new().resolve(&statements).unwrap();
+
+        let ast::Stmt::Block(outer) = &statements[1] else {
+            panic!("expected a block");
+        };
+        let ast::Stmt::Block(inner) = &outer.statements[1] else {
+            panic!("expected a block");
+        };
+        let ast::Stmt::Expression(a) = &inner.statements[0] else {
+            panic!("expected an expression statement");
+        };
+        let ast::Stmt::Expression(g) = &inner.statements[1] else {
+            panic!("expected an expression statement");
+        };
+        let ast::Stmt::Expression(assign) = &outer.statements[2] else {
+            panic!("expected an expression statement");
+        };
+
+        let ast::Expr::Variable(a) = &a.exp else {
+            panic!("expected a variable");
+        };
+        let ast::Expr::Variable(g) = &g.exp else {
+            panic!("expected a variable");
+        };
+        let ast::Expr::Assign(assign) = &assign.exp else {
+            panic!("expected an assignment");
+        };
+        assert_eq!(locals.get(&a.id), Some(&1));
+        assert_eq!(locals.get(&g.id), None);
+        assert_eq!(locals.get(&assign.id), Some(&0));
+    }
+
+    #[test]
+    fn own_initializer() {
+        assert_eq!(
+            messages("var a = 1;\n{\n  var a = a;\n}"),
+            vec!["line 3: can't read local variable in its own initializer"]
+        );
+        assert!(resolve("var a = 1; var a = a;").is_ok());
+    }
+
+    #[test]
+    fn redeclaration() {
+        assert_eq!(
+            messages("fun f(a) {\n  var b;\n  var b;\n  var a;\n}"),
+            vec![
+                "line 3: already a variable with this name in this scope",
+                "line 4: already a variable with this name in this scope",
+            ]
+        );
+        assert!(resolve("var a; var a;").is_ok());
+
+        let errors = resolve("{\n  var a = 1;\n  var a = 2;\n}").unwrap_err();
+        assert_eq!(errors[0].secondary[0].span.line, 2);
+        assert_eq!(errors[0].secondary[0].message, "first declared here");
+        assert_eq!(
+            errors[0].notes,
+            vec!["shadowing is only allowed in a nested scope"]
+        );
+    }
+
+    #[test]
+    fn top_level_return() {
+        assert_eq!(
+            messages("print 1;\nreturn 2;"),
+            vec!["line 2: can't return from top-level code"]
+        );
+        assert!(resolve("fun f() { return 2; }").is_ok());
+    }
+
+    #[test]
+    fn this_outside_class() {
+        assert_eq!(
+            messages("print this;\nfun f() {\n  this.a = 1;\n}"),
+            vec![
+                "line 1: can't use 'this' outside of a class",
+                "line 3: can't use 'this' outside of a class",
+            ]
+        );
+        assert!(resolve("class A { m() { fun f() { return this; } return f; } }").is_ok());
+    }
+
+    #[test]
+    fn return_value_from_initializer() {
+        assert_eq!(
+            messages("class A {\n  init() {\n    return 1;\n  }\n}"),
+            vec!["line 3: can't return a value from an initializer"]
+        );
+        assert!(resolve("class A { init() { return; } }").is_ok());
+    }
+
+    #[test]
+    fn inheritance_errors() {
+        assert_eq!(
+            messages("class A < A {}"),
+            vec!["line 1: a class can't inherit from itself"]
+        );
+        assert_eq!(
+            messages("super.m();\nclass A {\n  m() { super.m(); }\n}"),
+            vec![
+                "line 1: can't use 'super' outside of a class",
+                "line 3: can't use 'super' in a class with no superclass",
+            ]
+        );
+        assert!(resolve("class A {} class B < A { m() { super.m(); } }").is_ok());
+    }
+}
@@ -1,23 +1,79 @@
 use super::token;
+use std::fmt;
 use std::option::Option;
 
+#[derive(Debug, PartialEq, Clone)]
+pub enum LexErrorKind {
+    UnexpectedCharacter(char),
+    UnterminatedString,
+    MalformedNumber(String),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct LexError {
+    pub kind: LexErrorKind,
+    pub span: token::Span,
+}
+
+impl fmt::Display for LexErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LexErrorKind::UnexpectedCharacter(c) => write!(f, "unexpected character '{}'", c),
+            LexErrorKind::UnterminatedString => write!(f, "unterminated string"),
+            LexErrorKind::MalformedNumber(n) => write!(f, "malformed number '{}'", n),
+        }
+    }
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.span.line, self.kind)
+    }
+}
+
 pub struct Scanner {
     source: Vec<char>,
+    file: usize,
 
     cursor: usize,
     start: usize,
     end: usize,
     line: usize,
+    column: usize,
+
+    // Position of `cursor` in bytes, and where the current token started.
+    offset: usize,
+    start_offset: usize,
+    start_line: usize,
+    start_column: usize,
+
+    errors: Vec<LexError>,
+    comments: Vec<token::Span>,
 }
 
 impl Scanner {
-    pub fn new(source: &String) -> Self {
+    pub fn new(source: &str) -> Self {
+        Scanner::with_file(source, 0)
+    }
+
+    pub fn with_file(source: &str, file: usize) -> Self {
+        let source: Vec<char> = source.chars().collect();
+        let end = source.len();
+
         Scanner {
-            source: source.chars().collect(),
+            source,
+            file,
             cursor: 0,
             start: 0,
-            end: source.len(),
+            end,
             line: 1,
+            column: 1,
+            offset: 0,
+            start_offset: 0,
+            start_line: 1,
+            start_column: 1,
+            errors: Vec::new(),
+            comments: Vec::new(),
         }
     }
 
@@ -27,172 +83,83 @@ impl Scanner {
         let mut has_next = true;
 
         while has_next {
-            has_next = match tok.typ {
-                token::Type::EndOfFile => false,
-                _ => true,
-            };
+            has_next = !matches!(tok.typ, token::Type::EndOfFile);
             result.push(tok);
             tok = self.next();
         }
         result
     }
 
+    // Lexical errors found by `parse`. Scanning continues past each one, so
+    // the token stream stays usable for the parser.
+    pub fn errors(&self) -> &[LexError] {
+        &self.errors
+    }
+
+    // Spans of the line comments skipped between tokens. The parser never
+    // sees them, but tools such as highlighters keep them as trivia.
+    pub fn comments(&self) -> &[token::Span] {
+        &self.comments
+    }
+
     fn next(&mut self) -> token::Token {
+        loop {
+            if let Some(token) = self.scan_token() {
+                return token;
+            }
+        }
+    }
+
+    fn scan_token(&mut self) -> Option<token::Token> {
         self.skip_whitespace();
         self.start = self.cursor;
+        self.start_offset = self.offset;
+        self.start_line = self.line;
+        self.start_column = self.column;
         let current = self.advance();
 
         if let Some(c) = current {
-            match c {
-                '(' => token::Token {
-                    typ: token::Type::LeftParen,
-                    lexeme: None,
-                    literal: token::Value::Str(self.get_current_literal()),
-                    line: self.line,
-                },
-                ')' => token::Token {
-                    typ: token::Type::RightParen,
-                    lexeme: None,
-                    literal: token::Value::Str(self.get_current_literal()),
-                    line: self.line,
-                },
-                '{' => token::Token {
-                    typ: token::Type::LeftBrace,
-                    lexeme: None,
-                    literal: token::Value::Str(self.get_current_literal()),
-                    line: self.line,
-                },
-                '}' => token::Token {
-                    typ: token::Type::RightBrace,
-                    lexeme: None,
-                    literal: token::Value::Str(self.get_current_literal()),
-                    line: self.line,
-                },
-                ',' => token::Token {
-                    typ: token::Type::Comma,
-                    lexeme: None,
-                    literal: token::Value::Str(self.get_current_literal()),
-                    line: self.line,
-                },
-                '.' => token::Token {
-                    typ: token::Type::Dot,
-                    lexeme: None,
-                    literal: token::Value::Str(self.get_current_literal()),
-                    line: self.line,
-                },
-                ';' => token::Token {
-                    typ: token::Type::Semicolon,
-                    lexeme: None,
-                    literal: token::Value::Str(self.get_current_literal()),
-                    line: self.line,
-                },
-                '+' => token::Token {
-                    typ: token::Type::Plus,
-                    lexeme: None,
-                    literal: token::Value::Str(self.get_current_literal()),
-                    line: self.line,
-                },
-                '-' => token::Token {
-                    typ: token::Type::Minus,
-                    lexeme: None,
-                    literal: token::Value::Str(self.get_current_literal()),
-                    line: self.line,
-                },
-                '*' => token::Token {
-                    typ: token::Type::Star,
-                    lexeme: None,
-                    literal: token::Value::Str(self.get_current_literal()),
-                    line: self.line,
-                },
+            let token = match c {
+                '(' => self.token(token::Type::LeftParen),
+                ')' => self.token(token::Type::RightParen),
+                '{' => self.token(token::Type::LeftBrace),
+                '}' => self.token(token::Type::RightBrace),
+                ',' => self.token(token::Type::Comma),
+                '.' => self.token(token::Type::Dot),
+                ';' => self.token(token::Type::Semicolon),
+                '+' => self.token(token::Type::Plus),
+                '-' => self.token(token::Type::Minus),
+                '*' => self.token(token::Type::Star),
+                '/' => self.token(token::Type::Slash),
                 '!' => {
-                    if let Some(_) = self.advance_if_match('=') {
-                        token::Token {
-                            typ: token::Type::BangEqual,
-                            lexeme: None,
-                            literal: token::Value::Str(self.get_current_literal()),
-                            line: self.line,
-                        }
+                    if self.advance_if_match('=').is_some() {
+                        self.token(token::Type::BangEqual)
                     } else {
-                        token::Token {
-                            typ: token::Type::Bang,
-                            lexeme: None,
-                            literal: token::Value::Str(self.get_current_literal()),
-                            line: self.line,
-                        }
+                        self.token(token::Type::Bang)
                     }
                 }
                 '=' => {
-                    if let Some(_) = self.advance_if_match('=') {
-                        token::Token {
-                            typ: token::Type::EqualEqual,
-                            lexeme: None,
-                            literal: token::Value::Str(self.get_current_literal()),
-                            line: self.line,
-                        }
+                    if self.advance_if_match('=').is_some() {
+                        self.token(token::Type::EqualEqual)
                     } else {
-                        token::Token {
-                            typ: token::Type::Equal,
-                            lexeme: None,
-                            literal: token::Value::Str(self.get_current_literal()),
-                            line: self.line,
-                        }
+                        self.token(token::Type::Equal)
                     }
                 }
                 '<' => {
-                    if let Some(_) = self.advance_if_match('=') {
-                        token::Token {
-                            typ: token::Type::LessEqual,
-                            lexeme: None,
-                            literal: token::Value::Str(self.get_current_literal()),
-                            line: self.line,
-                        }
+                    if self.advance_if_match('=').is_some() {
+                        self.token(token::Type::LessEqual)
                     } else {
-                        token::Token {
-                            typ: token::Type::Less,
-                            lexeme: None,
-                            literal: token::Value::Str(self.get_current_literal()),
-                            line: self.line,
-                        }
+                        self.token(token::Type::Less)
                     }
                 }
                 '>' => {
-                    if let Some(_) = self.advance_if_match('=') {
-                        token::Token {
-                            typ: token::Type::GreaterEqual,
-                            lexeme: None,
-                            literal: token::Value::Str(self.get_current_literal()),
-                            line: self.line,
-                        }
-                    } else {
-                        token::Token {
-                            typ: token::Type::Greater,
-                            lexeme: None,
-                            literal: token::Value::Str(self.get_current_literal()),
-                            line: self.line,
-                        }
-                    }
-                }
-                '/' => {
-                    if let Some(_) = self.advance_if_match('/') {
-                        while let Some(skip) = self.peek() {
-                            if skip != '\n' && skip != '\r' {
-                                self.advance();
-                            } else {
-                                break;
-                            }
-                        }
-                        self.next()
+                    if self.advance_if_match('=').is_some() {
+                        self.token(token::Type::GreaterEqual)
                     } else {
-                        token::Token {
-                            typ: token::Type::Slash,
-                            lexeme: None,
-                            literal: token::Value::Str(self.get_current_literal()),
-                            line: self.line,
-                        }
+                        self.token(token::Type::Greater)
                     }
                 }
                 '"' => {
-                    self.start = self.cursor;
                     while let Some(skip) = self.peek() {
                         if skip != '"' {
                             self.advance();
@@ -200,19 +167,18 @@ impl Scanner {
                             break;
                         }
                     }
-                    self.advance();
-                    token::Token {
-                        typ: token::Type::String,
-                        lexeme: None,
-                        literal: token::Value::Str(self.get_current_literal()),
-                        line: self.line,
+                    let contents =
+                        String::from_iter(self.source[self.start + 1..self.cursor].iter());
+                    if self.advance().is_none() {
+                        self.error(LexErrorKind::UnterminatedString);
                     }
+                    self.token_with_literal(token::Type::String, token::Value::Str(contents))
                 }
                 '0'..='9' => {
                     let mut decimal = false;
 
                     while let Some(skip) = self.peek() {
-                        if skip.is_numeric() {
+                        if skip.is_ascii_digit() {
                             self.advance();
                         } else if skip == '.' && !decimal {
                             self.advance();
@@ -221,19 +187,25 @@ impl Scanner {
                             break;
                         }
                     }
-                    token::Token {
-                        typ: token::Type::Number,
-                        lexeme: None,
-                        literal: token::Value::Num(
-                            self.get_current_literal().parse::<f64>().unwrap(),
-                        ),
-                        line: self.line,
+                    let digits = self.get_current_literal();
+                    while let Some(skip) = self.peek() {
+                        if skip == '_' || skip.is_alphanumeric() {
+                            self.advance();
+                        } else {
+                            break;
+                        }
+                    }
+                    if self.cursor - self.start > digits.chars().count() {
+                        self.error(LexErrorKind::MalformedNumber(self.get_current_literal()));
                     }
+
+                    let value = digits.parse::<f64>().unwrap_or(0.0);
+                    self.token_with_literal(token::Type::Number, token::Value::Num(value))
                 }
                 _ => {
-                    if c == '-' || c.is_alphabetic() {
+                    if c == '_' || c.is_alphabetic() {
                         while let Some(skip) = self.peek() {
-                            if skip == '-' || skip.is_alphanumeric() {
+                            if skip == '_' || skip.is_alphanumeric() {
                                 self.advance();
                             } else {
                                 break;
@@ -262,31 +234,47 @@ impl Scanner {
                         let current_value = match current_type {
                             token::Type::True => token::Value::Bool(true),
                             token::Type::False => token::Value::Bool(false),
+                            token::Type::Nil => token::Value::None,
                             _ => token::Value::Str(current_literal),
                         };
-                        token::Token {
-                            typ: current_type,
-                            lexeme: None,
-                            literal: current_value,
-                            line: self.line,
-                        }
+                        self.token_with_literal(current_type, current_value)
                     } else {
-                        token::Token {
-                            typ: token::Type::EndOfFile,
-                            lexeme: None,
-                            literal: token::Value::Str(self.get_current_literal()),
-                            line: self.line,
-                        }
+                        self.error(LexErrorKind::UnexpectedCharacter(c));
+                        return None;
                     }
                 }
-            }
+            };
+            Some(token)
         } else {
-            token::Token {
-                typ: token::Type::EndOfFile,
-                lexeme: None,
-                literal: token::Value::None,
-                line: self.line,
-            }
+            Some(self.token_with_literal(token::Type::EndOfFile, token::Value::None))
+        }
+    }
+
+    fn error(&mut self, kind: LexErrorKind) {
+        let span = self.span();
+        self.errors.push(LexError { kind, span });
+    }
+
+    fn token(&self, typ: token::Type) -> token::Token {
+        self.token_with_literal(typ, token::Value::Str(self.get_current_literal()))
+    }
+
+    fn token_with_literal(&self, typ: token::Type, literal: token::Value) -> token::Token {
+        token::Token {
+            typ,
+            lexeme: self.get_current_literal(),
+            literal,
+            span: self.span(),
+        }
+    }
+
+    fn span(&self) -> token::Span {
+        token::Span {
+            file: self.file,
+            start: self.start_offset,
+            end: self.offset,
+            line: self.start_line,
+            column: self.start_column,
         }
     }
 
@@ -302,33 +290,48 @@ impl Scanner {
 
             if current == '\n' {
                 self.line += 1;
+                self.column = 1;
+            } else {
+                self.column += 1;
             }
             self.cursor += 1;
+            self.offset += current.len_utf8();
             Some(current)
         }
     }
 
     fn advance_if_match(&mut self, c: char) -> Option<char> {
-        if self.is_at_end() {
-            None
+        if self.peek() == Some(c) {
+            self.advance()
         } else {
-            let current = self.source[self.cursor];
-
-            if current == c {
-                self.cursor += 1;
-                Some(current)
-            } else {
-                None
-            }
+            None
         }
     }
 
+    // Skips whitespace and line comments between tokens.
     fn skip_whitespace(&mut self) {
-        while !self.is_at_end() && self.source[self.cursor].is_whitespace() {
-            if self.source[self.cursor] == '\n' {
-                self.line += 1;
+        while let Some(c) = self.peek() {
+            if c.is_whitespace() {
+                self.advance();
+            } else if c == '/' && self.peek_next() == Some('/') {
+                let (start, line, column) = (self.offset, self.line, self.column);
+                while let Some(skip) = self.peek() {
+                    if skip != '\n' && skip != '\r' {
+                        self.advance();
+                    } else {
+                        break;
+                    }
+                }
+                self.comments.push(token::Span {
+                    file: self.file,
+                    start,
+                    end: self.offset,
+                    line,
+                    column,
+                });
+            } else {
+                break;
             }
-            self.cursor += 1;
         }
     }
 
@@ -340,10 +343,12 @@ impl Scanner {
         }
     }
 
+    fn peek_next(&self) -> Option<char> {
+        self.source.get(self.cursor + 1).copied()
+    }
+
     fn get_current_literal(&self) -> String {
-        String::from_iter((&self.source[self.start..self.cursor]).iter())
-            .trim()
-            .replace('"', "")
+        String::from_iter(self.source[self.start..self.cursor].iter())
     }
 }
 
@@ -354,7 +359,7 @@ mod tests {
 
     #[test]
     fn empty_string() {
-        let mut scanner = Scanner::new(&"".to_string());
+        let mut scanner = Scanner::new("");
         let tokens = scanner.parse();
         assert_eq!(tokens.len(), 1);
         assert_eq!(tokens[0].typ, token::Type::EndOfFile);
@@ -362,7 +367,7 @@ mod tests {
 
     #[test]
     fn whitespace_string() {
-        let mut scanner = Scanner::new(&"        ".to_string());
+        let mut scanner = Scanner::new("        ");
         let tokens = scanner.parse();
         assert_eq!(tokens.len(), 1);
         assert_eq!(tokens[0].typ, token::Type::EndOfFile);
@@ -370,7 +375,7 @@ mod tests {
 
     #[test]
     fn single_characters() {
-        let mut scanner = Scanner::new(&"(){},.-+;*".to_string());
+        let mut scanner = Scanner::new("(){},.-+;*");
         let tokens = scanner.parse();
 
         assert_eq!(tokens.len(), 11);
@@ -396,7 +401,7 @@ mod tests {
 
     #[test]
     fn one_or_two_characters() {
-        let mut scanner = Scanner::new(&"! != = == > >= < <=".to_string());
+        let mut scanner = Scanner::new("! != = == > >= < <=");
         let tokens = scanner.parse();
 
         assert_eq!(tokens.len(), 9);
@@ -421,8 +426,7 @@ mod tests {
     #[test]
     fn keywords() {
         let mut scanner = Scanner::new(
-            &"and class else fun for if nil or print return super this true false var while"
-                .to_string(),
+            "and class else fun for if nil or print return super this true false var while",
         );
         let tokens = scanner.parse();
 
@@ -456,11 +460,19 @@ mod tests {
     #[test]
     fn string_literals() {
         let mut scanner = Scanner::new(
-            &"\"This is a string literal\" \"This is another string literal without ending quotes"
-                .to_string(),
+            "\"This is a string literal\" \"This is another string literal without ending quotes",
         );
         let tokens = scanner.parse();
 
+        assert_eq!(
+            scanner
+                .errors()
+                .iter()
+                .map(|e| e.kind.clone())
+                .collect::<Vec<LexErrorKind>>(),
+            vec![LexErrorKind::UnterminatedString]
+        );
+
         assert_eq!(tokens.len(), 3);
 
         let expected_types: Vec<token::Type> = vec![
@@ -483,7 +495,7 @@ mod tests {
 
     #[test]
     fn numbers() {
-        let mut scanner = Scanner::new(&"1 1.1 1..1".to_string());
+        let mut scanner = Scanner::new("1 1.1 1..1");
         let tokens = scanner.parse();
 
         assert_eq!(tokens.len(), 6);
@@ -502,22 +514,22 @@ mod tests {
         );
 
         if let token::Value::Num(v) = tokens[0].literal {
-            assert_eq!(1.0 as f64, v);
+            assert_eq!(1.0, v);
         }
         if let token::Value::Num(v) = tokens[1].literal {
-            assert_eq!(1.1 as f64, v);
+            assert_eq!(1.1, v);
         }
         if let token::Value::Num(v) = tokens[2].literal {
-            assert_eq!(1.0 as f64, v);
+            assert_eq!(1.0, v);
         }
         if let token::Value::Num(v) = tokens[4].literal {
-            assert_eq!(1.0 as f64, v);
+            assert_eq!(1.0, v);
         }
     }
 
     #[test]
     fn expressions() {
-        let mut scanner = Scanner::new(&"var v1 = true; var v2 = 1.1;".to_string());
+        let mut scanner = Scanner::new("var v1 = true; var v2 = 1.1;");
         let tokens = scanner.parse();
 
         assert_eq!(tokens.len(), 11);
@@ -544,19 +556,19 @@ mod tests {
             assert_eq!("v1", v);
         }
         if let token::Value::Bool(v) = tokens[3].literal {
-            assert_eq!(true, v);
+            assert!(v);
         }
         if let token::Value::Str(v) = &tokens[6].literal {
             assert_eq!("v2", v);
         }
         if let token::Value::Num(v) = tokens[8].literal {
-            assert_eq!(1.1 as f64, v);
+            assert_eq!(1.1, v);
         }
     }
 
     #[test]
     fn functions() {
-        let mut scanner = Scanner::new(&"fun main() {}".to_string());
+        let mut scanner = Scanner::new("fun main() {}");
         let tokens = scanner.parse();
 
         assert_eq!(tokens.len(), 7);
@@ -582,7 +594,7 @@ mod tests {
 
     #[test]
     fn classes() {
-        let mut scanner = Scanner::new(&"class Car {}".to_string());
+        let mut scanner = Scanner::new("class Car {}");
         let tokens = scanner.parse();
 
         assert_eq!(tokens.len(), 5);
@@ -607,7 +619,7 @@ mod tests {
     #[test]
     fn full_program() {
         let mut scanner = Scanner::new(
-            &r#"
+            r#"
             
                 class Person {
                     Person(name, age, married) {
@@ -624,8 +636,7 @@ mod tests {
                     print p.married;
                 }
 
-            "#
-            .to_string(),
+            "#,
         );
         let tokens = scanner.parse();
 
@@ -718,4 +729,169 @@ mod tests {
             tokens.iter().map(|v| v.typ).collect::<Vec<token::Type>>()
         );
     }
+
+    #[test]
+    fn identifiers() {
+        let tokens = Scanner::new("_a1 snake_case a-b").parse();
+
+        let expected_types: Vec<token::Type> = vec![
+            token::Type::Identifier,
+            token::Type::Identifier,
+            token::Type::Identifier,
+            token::Type::Minus,
+            token::Type::Identifier,
+            token::Type::EndOfFile,
+        ];
+        assert_eq!(
+            expected_types,
+            tokens.iter().map(|v| v.typ).collect::<Vec<token::Type>>()
+        );
+        assert_eq!(
+            vec!["_a1", "snake_case", "a", "-", "b", ""],
+            tokens.iter().map(|v| v.name()).collect::<Vec<&str>>()
+        );
+    }
+
+    #[test]
+    fn string_literals_keep_whitespace() {
+        let tokens = Scanner::new("\" padded \"").parse();
+
+        assert_eq!(tokens[0].literal, token::Value::Str(" padded ".to_owned()));
+    }
+
+    #[test]
+    fn lexemes_are_source_slices() {
+        let tokens = Scanner::new("print \"hi\" >= 1.5;").parse();
+
+        assert_eq!(
+            vec!["print", "\"hi\"", ">=", "1.5", ";", ""],
+            tokens
+                .iter()
+                .map(|v| v.lexeme.as_str())
+                .collect::<Vec<&str>>()
+        );
+        assert_eq!(tokens[1].literal, token::Value::Str("hi".to_owned()));
+    }
+
+    #[test]
+    fn spans() {
+        let tokens = Scanner::with_file("var a;\n  // note\n  a = 1;", 3).parse();
+
+        assert_eq!(
+            tokens[0].span,
+            token::Span {
+                file: 3,
+                start: 0,
+                end: 3,
+                line: 1,
+                column: 1,
+            }
+        );
+        assert_eq!(
+            tokens[3].span,
+            token::Span {
+                file: 3,
+                start: 19,
+                end: 20,
+                line: 3,
+                column: 3,
+            }
+        );
+        assert_eq!(tokens[5].span.column, 7);
+        assert_eq!(tokens[7].span.start, 25);
+        assert!(tokens[7].span.is_empty());
+    }
+
+    #[test]
+    fn spans_use_byte_offsets() {
+        let source = "\"héllo\" + x";
+        let tokens = Scanner::new(source).parse();
+
+        assert_eq!(
+            &source[tokens[0].span.start..tokens[0].span.end],
+            "\"héllo\""
+        );
+        assert_eq!(tokens[1].span.start, 9);
+        assert_eq!(tokens[1].span.column, 9);
+        assert_eq!(&source[tokens[2].span.start..tokens[2].span.end], "x");
+    }
+
+    #[test]
+    fn multiline_strings_start_on_their_first_line() {
+        let tokens = Scanner::new("\"a\nb\" c").parse();
+
+        assert_eq!(tokens[0].span.line, 1);
+        assert_eq!(tokens[0].span.len(), 5);
+        assert_eq!(tokens[1].span.line, 2);
+        assert_eq!(tokens[1].span.column, 4);
+    }
+
+    #[test]
+    fn unexpected_characters_do_not_stop_scanning() {
+        let mut scanner = Scanner::new("var a = 1 @ 2;\n# print a;");
+        let tokens = scanner.parse();
+
+        assert_eq!(
+            vec!["var", "a", "=", "1", "2", ";", "print", "a", ";", ""],
+            tokens
+                .iter()
+                .map(|v| v.lexeme.as_str())
+                .collect::<Vec<&str>>()
+        );
+        assert_eq!(
+            scanner
+                .errors()
+                .iter()
+                .map(|e| e.to_string())
+                .collect::<Vec<String>>(),
+            vec![
+                "line 1: unexpected character '@'",
+                "line 2: unexpected character '#'"
+            ]
+        );
+        assert_eq!(scanner.errors()[1].span.start, 15);
+        assert_eq!(scanner.errors()[1].span.column, 1);
+    }
+
+    #[test]
+    fn malformed_numbers() {
+        let mut scanner = Scanner::new("print 12px + 3;");
+        let tokens = scanner.parse();
+
+        assert_eq!(tokens[1].typ, token::Type::Number);
+        assert_eq!(tokens[1].lexeme, "12px");
+        assert_eq!(tokens[2].typ, token::Type::Plus);
+        assert_eq!(
+            scanner.errors(),
+            &[LexError {
+                kind: LexErrorKind::MalformedNumber("12px".to_owned()),
+                span: tokens[1].span,
+            }]
+        );
+    }
+
+    #[test]
+    fn every_error_is_reported() {
+        let mut scanner = Scanner::new("@ 1a $ \"open");
+        scanner.parse();
+
+        assert_eq!(scanner.errors().len(), 4);
+    }
+
+    #[test]
+    fn comments_are_kept_as_trivia() {
+        let source = "// first\nprint 1; // second\r\n//";
+        let mut scanner = Scanner::new(source);
+        let tokens = scanner.parse();
+
+        assert_eq!(tokens.len(), 4);
+        assert_eq!(
+            scanner
+                .comments()
+                .iter()
+                .map(|c| (&source[c.start..c.end], c.line, c.column))
+                .collect::<Vec<_>>(),
+            vec![("// first", 1, 1), ("// second", 2, 10), ("//", 3, 1)]
+        );
+    }
 }
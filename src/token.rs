@@ -1,5 +1,3 @@
-use std::option::Option;
-
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum Type {
     // Single character tokens
@@ -59,10 +57,150 @@ pub enum Value {
     None,
 }
 
+// A range of source text. Offsets are in bytes, `end` exclusive; `line` and
+// `column` are 1-based and point at the first character.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct Span {
+    pub file: usize,
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Span {
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    // The smallest span covering both `self` and `other`.
+    pub fn to(&self, other: Span) -> Span {
+        if other.start < self.start {
+            return other.to(*self);
+        }
+        Span {
+            end: self.end.max(other.end),
+            ..*self
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub struct Token {
     pub typ: Type,
-    pub lexeme: Option<String>,
+    pub lexeme: String,
     pub literal: Value,
-    pub line: usize,
+    pub span: Span,
+}
+
+impl Token {
+    pub fn name(&self) -> &str {
+        match &self.literal {
+            Value::Str(s) => s,
+            _ => "",
+        }
+    }
+}
+
+// Broad groups of token types, following the book's grouping except that
+// `true`, `false` and `nil` are literals rather than keywords.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum Category {
+    Punctuation,
+    Operator,
+    Identifier,
+    Literal,
+    Keyword,
+    EndOfFile,
+}
+
+impl Type {
+    pub fn category(&self) -> Category {
+        match self {
+            Type::LeftParen
+            | Type::RightParen
+            | Type::LeftBrace
+            | Type::RightBrace
+            | Type::Comma
+            | Type::Dot
+            | Type::Semicolon => Category::Punctuation,
+            Type::Minus
+            | Type::Plus
+            | Type::Slash
+            | Type::Star
+            | Type::Bang
+            | Type::BangEqual
+            | Type::Equal
+            | Type::EqualEqual
+            | Type::Greater
+            | Type::GreaterEqual
+            | Type::Less
+            | Type::LessEqual => Category::Operator,
+            Type::Identifier => Category::Identifier,
+            Type::String | Type::Number | Type::True | Type::False | Type::Nil => Category::Literal,
+            Type::And
+            | Type::Class
+            | Type::Else
+            | Type::Fun
+            | Type::For
+            | Type::If
+            | Type::Or
+            | Type::Print
+            | Type::Return
+            | Type::Super
+            | Type::This
+            | Type::Var
+            | Type::While => Category::Keyword,
+            Type::EndOfFile => Category::EndOfFile,
+        }
+    }
+
+    // How the token type reads in messages, e.g. "expected one of ...".
+    pub fn describe(&self) -> &'static str {
+        match self {
+            Type::LeftParen => "'('",
+            Type::RightParen => "')'",
+            Type::LeftBrace => "'{'",
+            Type::RightBrace => "'}'",
+            Type::Comma => "','",
+            Type::Dot => "'.'",
+            Type::Minus => "'-'",
+            Type::Plus => "'+'",
+            Type::Semicolon => "';'",
+            Type::Slash => "'/'",
+            Type::Star => "'*'",
+            Type::Bang => "'!'",
+            Type::BangEqual => "'!='",
+            Type::Equal => "'='",
+            Type::EqualEqual => "'=='",
+            Type::Greater => "'>'",
+            Type::GreaterEqual => "'>='",
+            Type::Less => "'<'",
+            Type::LessEqual => "'<='",
+            Type::Identifier => "identifier",
+            Type::String => "string",
+            Type::Number => "number",
+            Type::And => "'and'",
+            Type::Class => "'class'",
+            Type::Else => "'else'",
+            Type::Fun => "'fun'",
+            Type::For => "'for'",
+            Type::If => "'if'",
+            Type::Nil => "'nil'",
+            Type::Or => "'or'",
+            Type::Print => "'print'",
+            Type::Return => "'return'",
+            Type::Super => "'super'",
+            Type::This => "'this'",
+            Type::True => "'true'",
+            Type::False => "'false'",
+            Type::Var => "'var'",
+            Type::While => "'while'",
+            Type::EndOfFile => "end of file",
+        }
+    }
 }
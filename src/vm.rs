@@ -0,0 +1,803 @@
+use super::callable;
+use super::chunk::{Constant, FunctionProto, OpCode};
+use super::heap::{
+    BoundMethod, Class, Closure, Heap, Instance, Native, Obj, ObjRef, Upvalue, Value,
+};
+use super::interpreter::{RuntimeError, RuntimeErrorKind};
+use super::limits::{Budget, Limit, Limits, MAX_CALL_DEPTH};
+use std::collections::HashMap;
+use std::io;
+use std::io::Write;
+use std::rc::Rc;
+
+// One frame more than calls may nest, for the script itself.
+const FRAMES_MAX: usize = MAX_CALL_DEPTH + 1;
+
+struct CallFrame {
+    closure: ObjRef,
+    function: Rc<FunctionProto>,
+    ip: usize,
+    slots: usize,
+}
+
+pub struct Vm {
+    output: Box<dyn Write>,
+    heap: Heap,
+    stack: Vec<Value>,
+    frames: Vec<CallFrame>,
+    globals: HashMap<Rc<str>, Value>,
+    open_upvalues: Vec<ObjRef>,
+    budget: Budget,
+}
+
+impl Default for Vm {
+    fn default() -> Self {
+        Vm::new()
+    }
+}
+
+impl Vm {
+    // Public
+    pub fn new() -> Self {
+        Vm::with_output(Box::new(io::stdout()))
+    }
+
+    pub fn with_output(output: Box<dyn Write>) -> Self {
+        let mut vm = Vm {
+            output,
+            heap: Heap::new(),
+            stack: Vec::with_capacity(256),
+            frames: Vec::with_capacity(64),
+            globals: HashMap::new(),
+            open_upvalues: Vec::new(),
+            budget: Budget::default(),
+        };
+
+        vm.define_native(Native {
+            name: "clock",
+            arity: 0,
+            function: |_| Value::Num(callable::clock()),
+        });
+        vm
+    }
+
+    // Collects on every allocation so that missing roots show up immediately.
+    pub fn set_gc_stress(&mut self, stress: bool) {
+        self.heap.set_stress(stress);
+    }
+
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.budget = Budget::new(limits);
+    }
+
+    pub fn interpret(&mut self, function: Rc<FunctionProto>) -> Result<(), RuntimeError> {
+        self.budget.start();
+        let closure = self.alloc(Obj::Closure(Closure {
+            function,
+            upvalues: Vec::new(),
+        }));
+        self.stack.push(Value::Obj(closure));
+
+        let result = self.call_closure(closure, 0).and_then(|_| self.run());
+        if result.is_err() {
+            // Closures that escaped into globals must keep their captured
+            // values once the stack they point into is gone.
+            self.close_upvalues(0);
+            self.stack.clear();
+            self.frames.clear();
+            self.open_upvalues.clear();
+        }
+        result
+    }
+
+    // Execute
+    fn run(&mut self) -> Result<(), RuntimeError> {
+        loop {
+            self.check_limits()?;
+            let op = match OpCode::from_byte(self.read_byte()) {
+                Some(op) => op,
+                None => return Err(self.error("unknown opcode")),
+            };
+
+            match op {
+                OpCode::Constant => match self.read_constant() {
+                    Constant::Num(n) => self.push(Value::Num(n)),
+                    Constant::Str(s) => {
+                        let r = self.alloc(Obj::String(s));
+                        self.push(Value::Obj(r));
+                    }
+                    Constant::Function(_) => self.push(Value::Nil),
+                },
+                OpCode::Nil => self.push(Value::Nil),
+                OpCode::True => self.push(Value::Bool(true)),
+                OpCode::False => self.push(Value::Bool(false)),
+                OpCode::Pop => {
+                    self.pop();
+                }
+                OpCode::GetLocal => {
+                    let slot = self.frame().slots + self.read_byte() as usize;
+                    self.push(self.stack[slot]);
+                }
+                OpCode::SetLocal => {
+                    let slot = self.frame().slots + self.read_byte() as usize;
+                    self.stack[slot] = self.peek(0);
+                }
+                OpCode::GetGlobal => {
+                    let name = self.read_string();
+                    match self.globals.get(&name) {
+                        Some(value) => self.push(*value),
+                        None => return Err(self.undefined_variable(&name)),
+                    }
+                }
+                OpCode::DefineGlobal => {
+                    let name = self.read_string();
+                    self.globals.insert(name, self.peek(0));
+                    self.pop();
+                }
+                OpCode::SetGlobal => {
+                    let name = self.read_string();
+                    if !self.globals.contains_key(&name) {
+                        return Err(self.undefined_variable(&name));
+                    }
+                    self.globals.insert(name, self.peek(0));
+                }
+                OpCode::GetUpvalue => {
+                    let index = self.read_byte() as usize;
+                    let upvalue = self.frame_upvalue(index);
+                    let value = match self.heap.get(upvalue) {
+                        Obj::Upvalue(Upvalue::Open(slot)) => self.stack[*slot],
+                        Obj::Upvalue(Upvalue::Closed(value)) => *value,
+                        _ => Value::Nil,
+                    };
+                    self.push(value);
+                }
+                OpCode::SetUpvalue => {
+                    let index = self.read_byte() as usize;
+                    let upvalue = self.frame_upvalue(index);
+                    let value = self.peek(0);
+                    match self.heap.get_mut(upvalue) {
+                        Obj::Upvalue(Upvalue::Open(slot)) => {
+                            let slot = *slot;
+                            self.stack[slot] = value;
+                        }
+                        Obj::Upvalue(Upvalue::Closed(closed)) => *closed = value,
+                        _ => (),
+                    }
+                }
+                OpCode::GetProperty => {
+                    let name = self.read_string();
+                    let class = match self.peek(0) {
+                        Value::Obj(r) => match self.heap.get(r) {
+                            Obj::Instance(instance) => {
+                                if let Some(value) = instance.fields.get(&name) {
+                                    let value = *value;
+                                    self.pop();
+                                    self.push(value);
+                                    continue;
+                                }
+                                Some(instance.class)
+                            }
+                            _ => None,
+                        },
+                        _ => None,
+                    };
+
+                    match class {
+                        Some(class) => self.bind_method(class, &name)?,
+                        None => return Err(self.error("only instances have properties")),
+                    }
+                }
+                OpCode::SetProperty => {
+                    let name = self.read_string();
+                    let value = self.peek(0);
+                    let instance = match self.peek(1) {
+                        Value::Obj(r) if matches!(self.heap.get(r), Obj::Instance(_)) => r,
+                        _ => return Err(self.error("only instances have fields")),
+                    };
+                    if let Obj::Instance(object) = self.heap.get_mut(instance) {
+                        object.fields.insert(name, value);
+                    }
+                    self.heap.resize(instance);
+                    self.pop();
+                    self.pop();
+                    self.push(value);
+                }
+                OpCode::GetSuper => {
+                    let name = self.read_string();
+                    match self.pop() {
+                        Value::Obj(superclass) => self.bind_method(superclass, &name)?,
+                        _ => return Err(self.error("superclass must be a class")),
+                    }
+                }
+                OpCode::Equal => {
+                    let b = self.pop();
+                    let a = self.pop();
+                    self.push(Value::Bool(self.values_equal(a, b)));
+                }
+                OpCode::NotEqual => {
+                    let b = self.pop();
+                    let a = self.pop();
+                    self.push(Value::Bool(!self.values_equal(a, b)));
+                }
+                OpCode::Greater => {
+                    let (a, b) = self.number_operands()?;
+                    self.push(Value::Bool(a > b));
+                }
+                OpCode::GreaterEqual => {
+                    let (a, b) = self.number_operands()?;
+                    self.push(Value::Bool(a >= b));
+                }
+                OpCode::Less => {
+                    let (a, b) = self.number_operands()?;
+                    self.push(Value::Bool(a < b));
+                }
+                OpCode::LessEqual => {
+                    let (a, b) = self.number_operands()?;
+                    self.push(Value::Bool(a <= b));
+                }
+                OpCode::Add => match (self.peek(1), self.peek(0)) {
+                    (Value::Num(a), Value::Num(b)) => {
+                        self.pop();
+                        self.pop();
+                        self.push(Value::Num(a + b));
+                    }
+                    (Value::Obj(a), Value::Obj(b)) => match (self.heap.get(a), self.heap.get(b)) {
+                        (Obj::String(a), Obj::String(b)) => {
+                            let s: Rc<str> = Rc::from(format!("{}{}", a, b));
+                            let r = self.alloc(Obj::String(s));
+                            self.pop();
+                            self.pop();
+                            self.push(Value::Obj(r));
+                        }
+                        _ => return Err(self.error("operands must be two numbers or two strings")),
+                    },
+                    _ => return Err(self.error("operands must be two numbers or two strings")),
+                },
+                OpCode::Subtract => {
+                    let (a, b) = self.number_operands()?;
+                    self.push(Value::Num(a - b));
+                }
+                OpCode::Multiply => {
+                    let (a, b) = self.number_operands()?;
+                    self.push(Value::Num(a * b));
+                }
+                OpCode::Divide => {
+                    let (a, b) = self.number_operands()?;
+                    self.push(Value::Num(a / b));
+                }
+                OpCode::Not => {
+                    let value = self.pop();
+                    self.push(Value::Bool(value.is_falsey()));
+                }
+                OpCode::Negate => match self.peek(0) {
+                    Value::Num(n) => {
+                        self.pop();
+                        self.push(Value::Num(-n));
+                    }
+                    _ => return Err(self.error("operand must be a number")),
+                },
+                OpCode::Print => {
+                    let value = self.pop();
+                    let text = self.format_value(value);
+                    if let Err(err) = writeln!(self.output, "{}", text) {
+                        return Err(self.error(&err.to_string()));
+                    }
+                }
+                OpCode::Jump => {
+                    let offset = self.read_u16();
+                    self.frame_mut().ip += offset;
+                }
+                OpCode::JumpIfFalse => {
+                    let offset = self.read_u16();
+                    if self.peek(0).is_falsey() {
+                        self.frame_mut().ip += offset;
+                    }
+                }
+                OpCode::Loop => {
+                    let offset = self.read_u16();
+                    self.frame_mut().ip -= offset;
+                }
+                OpCode::Call => {
+                    let argc = self.read_byte() as usize;
+                    self.call_value(self.peek(argc), argc)?;
+                }
+                OpCode::Closure => {
+                    let function = match self.read_constant() {
+                        Constant::Function(function) => function,
+                        _ => return Err(self.error("expected a function constant")),
+                    };
+
+                    let mut upvalues = Vec::with_capacity(function.upvalue_count);
+                    for _ in 0..function.upvalue_count {
+                        let is_local = self.read_byte() == 1;
+                        let index = self.read_byte() as usize;
+                        if is_local {
+                            let slot = self.frame().slots + index;
+                            upvalues.push(self.capture_upvalue(slot));
+                        } else {
+                            upvalues.push(self.frame_upvalue(index));
+                        }
+                    }
+
+                    let closure = self.alloc(Obj::Closure(Closure { function, upvalues }));
+                    self.push(Value::Obj(closure));
+                }
+                OpCode::CloseUpvalue => {
+                    self.close_upvalues(self.stack.len() - 1);
+                    self.pop();
+                }
+                OpCode::Return => {
+                    let result = self.pop();
+                    let slots = self.frame().slots;
+                    self.close_upvalues(slots);
+                    self.frames.pop();
+                    self.stack.truncate(slots);
+
+                    if self.frames.is_empty() {
+                        return Ok(());
+                    }
+                    self.push(result);
+                }
+                OpCode::Class => {
+                    let name = self.read_string();
+                    let class = self.alloc(Obj::Class(Class {
+                        name,
+                        methods: HashMap::new(),
+                    }));
+                    self.push(Value::Obj(class));
+                }
+                OpCode::Inherit => {
+                    let methods = match self.peek(1) {
+                        Value::Obj(r) => match self.heap.get(r) {
+                            Obj::Class(superclass) => Some(superclass.methods.clone()),
+                            _ => None,
+                        },
+                        _ => None,
+                    };
+                    let methods = match methods {
+                        Some(methods) => methods,
+                        None => return Err(self.error("superclass must be a class")),
+                    };
+
+                    if let Value::Obj(r) = self.peek(0) {
+                        if let Obj::Class(subclass) = self.heap.get_mut(r) {
+                            subclass.methods.extend(methods);
+                        }
+                    }
+                    self.pop();
+                }
+                OpCode::Method => {
+                    let name = self.read_string();
+                    if let (Value::Obj(method), Value::Obj(class)) = (self.peek(0), self.peek(1)) {
+                        if let Obj::Class(object) = self.heap.get_mut(class) {
+                            object.methods.insert(name, method);
+                        }
+                        self.heap.resize(class);
+                    }
+                    self.pop();
+                }
+            }
+        }
+    }
+
+    fn call_value(&mut self, callee: Value, argc: usize) -> Result<(), RuntimeError> {
+        let r = match callee {
+            Value::Obj(r) => r,
+            _ => return Err(self.error("can only call functions and classes")),
+        };
+        let base = self.stack.len() - argc - 1;
+
+        match self.heap.get(r) {
+            Obj::Closure(_) => self.call_closure(r, argc),
+            Obj::Native(native) => {
+                if argc != native.arity {
+                    return Err(self.arity_error(native.arity, argc));
+                }
+                let result = (native.function)(&self.stack[base + 1..]);
+                self.stack.truncate(base);
+                self.push(result);
+                Ok(())
+            }
+            Obj::Class(class) => {
+                let init = class.methods.get("init").copied();
+                let instance = self.alloc(Obj::Instance(Instance {
+                    class: r,
+                    fields: HashMap::new(),
+                }));
+                self.stack[base] = Value::Obj(instance);
+
+                match init {
+                    Some(init) => self.call_closure(init, argc),
+                    None if argc != 0 => Err(self.arity_error(0, argc)),
+                    None => Ok(()),
+                }
+            }
+            Obj::BoundMethod(bound) => {
+                let method = bound.method;
+                self.stack[base] = bound.receiver;
+                self.call_closure(method, argc)
+            }
+            _ => Err(self.error("can only call functions and classes")),
+        }
+    }
+
+    fn call_closure(&mut self, closure: ObjRef, argc: usize) -> Result<(), RuntimeError> {
+        let function = match self.heap.get(closure) {
+            Obj::Closure(c) => c.function.clone(),
+            _ => return Err(self.error("can only call functions and classes")),
+        };
+
+        if argc != function.arity {
+            return Err(self.arity_error(function.arity, argc));
+        }
+        // The script's own frame is not a call.
+        if let Err(limit) = self.budget.check_call_depth(self.frames.len()) {
+            return Err(self.limit_error(limit));
+        }
+        if self.frames.len() >= FRAMES_MAX {
+            return Err(self.error("stack overflow"));
+        }
+
+        self.frames.push(CallFrame {
+            closure,
+            function,
+            ip: 0,
+            slots: self.stack.len() - argc - 1,
+        });
+        Ok(())
+    }
+
+    fn bind_method(&mut self, class: ObjRef, name: &Rc<str>) -> Result<(), RuntimeError> {
+        let method = match self.heap.get(class) {
+            Obj::Class(class) => class.methods.get(name).copied(),
+            _ => None,
+        };
+
+        match method {
+            Some(method) => {
+                let bound = self.alloc(Obj::BoundMethod(BoundMethod {
+                    receiver: self.peek(0),
+                    method,
+                }));
+                self.pop();
+                self.push(Value::Obj(bound));
+                Ok(())
+            }
+            None => Err(self.error(&format!("undefined property '{}'", name))),
+        }
+    }
+
+    fn capture_upvalue(&mut self, slot: usize) -> ObjRef {
+        for upvalue in &self.open_upvalues {
+            if let Obj::Upvalue(Upvalue::Open(open)) = self.heap.get(*upvalue) {
+                if *open == slot {
+                    return *upvalue;
+                }
+            }
+        }
+
+        let upvalue = self.alloc(Obj::Upvalue(Upvalue::Open(slot)));
+        self.open_upvalues.push(upvalue);
+        upvalue
+    }
+
+    fn close_upvalues(&mut self, last: usize) {
+        let stack = &self.stack;
+        let heap = &mut self.heap;
+
+        self.open_upvalues
+            .retain(|upvalue| match heap.get_mut(*upvalue) {
+                Obj::Upvalue(u) => match u {
+                    Upvalue::Open(slot) if *slot >= last => {
+                        *u = Upvalue::Closed(stack[*slot]);
+                        false
+                    }
+                    _ => true,
+                },
+                _ => false,
+            });
+    }
+
+    // Memory
+    fn alloc(&mut self, obj: Obj) -> ObjRef {
+        if self.heap.should_collect() {
+            self.collect_garbage();
+        }
+        self.heap.alloc(obj)
+    }
+
+    // Counts a step and checks the heap, collecting once before giving up.
+    fn check_limits(&mut self) -> Result<(), RuntimeError> {
+        if let Err(limit) = self.budget.step() {
+            return Err(self.limit_error(limit));
+        }
+        if self.budget.check_heap(self.heap.bytes()).is_err() {
+            self.collect_garbage();
+            if let Err(limit) = self.budget.check_heap(self.heap.bytes()) {
+                return Err(self.limit_error(limit));
+            }
+        }
+        Ok(())
+    }
+
+    fn collect_garbage(&mut self) {
+        for value in &self.stack {
+            self.heap.mark_value(*value);
+        }
+        for value in self.globals.values() {
+            self.heap.mark_value(*value);
+        }
+        for frame in &self.frames {
+            self.heap.mark_object(frame.closure);
+        }
+        for upvalue in &self.open_upvalues {
+            self.heap.mark_object(*upvalue);
+        }
+        self.heap.collect();
+    }
+
+    fn define_native(&mut self, native: Native) {
+        let name = Rc::from(native.name);
+        let r = self.alloc(Obj::Native(native));
+        self.globals.insert(name, Value::Obj(r));
+    }
+
+    // Values
+
+    fn values_equal(&self, a: Value, b: Value) -> bool {
+        match (a, b) {
+            (Value::Nil, Value::Nil) => true,
+            (Value::Bool(a), Value::Bool(b)) => a == b,
+            (Value::Num(a), Value::Num(b)) => a == b,
+            (Value::Obj(a), Value::Obj(b)) => match (self.heap.get(a), self.heap.get(b)) {
+                (Obj::String(a), Obj::String(b)) => a == b,
+                _ => a == b,
+            },
+            _ => false,
+        }
+    }
+
+    fn format_value(&self, value: Value) -> String {
+        match value {
+            Value::Nil => "nil".to_owned(),
+            Value::Bool(b) => b.to_string(),
+            Value::Num(n) => n.to_string(),
+            Value::Obj(r) => match self.heap.get(r) {
+                Obj::String(s) => s.to_string(),
+                Obj::Native(_) => "<native fn>".to_owned(),
+                Obj::Closure(c) => format!("<fn {}>", c.function.name),
+                Obj::Upvalue(_) => "upvalue".to_owned(),
+                Obj::Class(c) => c.name.to_string(),
+                Obj::Instance(i) => match self.heap.get(i.class) {
+                    Obj::Class(c) => format!("{} instance", c.name),
+                    _ => "instance".to_owned(),
+                },
+                Obj::BoundMethod(b) => self.format_value(Value::Obj(b.method)),
+            },
+        }
+    }
+
+    fn number_operands(&mut self) -> Result<(f64, f64), RuntimeError> {
+        match (self.peek(1), self.peek(0)) {
+            (Value::Num(a), Value::Num(b)) => {
+                self.pop();
+                self.pop();
+                Ok((a, b))
+            }
+            _ => Err(self.error("operands must be numbers")),
+        }
+    }
+
+    // Utilities
+
+    fn frame(&self) -> &CallFrame {
+        &self.frames[self.frames.len() - 1]
+    }
+
+    fn frame_mut(&mut self) -> &mut CallFrame {
+        let top = self.frames.len() - 1;
+        &mut self.frames[top]
+    }
+
+    fn frame_upvalue(&self, index: usize) -> ObjRef {
+        match self.heap.get(self.frame().closure) {
+            Obj::Closure(c) => c.upvalues[index],
+            _ => unreachable!("call frames always hold closures"),
+        }
+    }
+
+    fn read_byte(&mut self) -> u8 {
+        let frame = self.frame_mut();
+        let byte = frame.function.chunk.code[frame.ip];
+        frame.ip += 1;
+        byte
+    }
+
+    fn read_u16(&mut self) -> usize {
+        let high = self.read_byte() as usize;
+        let low = self.read_byte() as usize;
+        (high << 8) | low
+    }
+
+    fn read_constant(&mut self) -> Constant {
+        let index = self.read_u16();
+        self.frame().function.chunk.constants[index].clone()
+    }
+
+    fn read_string(&mut self) -> Rc<str> {
+        match self.read_constant() {
+            Constant::Str(s) => s,
+            _ => Rc::from(""),
+        }
+    }
+
+    fn push(&mut self, value: Value) {
+        self.stack.push(value);
+    }
+
+    fn pop(&mut self) -> Value {
+        // The compiler balances every pop with a push, so an empty stack
+        // means the bytecode is broken.
+        self.stack.pop().expect("value stack underflow")
+    }
+
+    fn peek(&self, distance: usize) -> Value {
+        self.stack[self.stack.len() - 1 - distance]
+    }
+
+    fn error(&self, message: &str) -> RuntimeError {
+        let Some(frame) = self.frames.last() else {
+            return RuntimeError::on_line(0, message);
+        };
+        let chunk = &frame.function.chunk;
+        let offset = frame.ip.saturating_sub(1);
+        let error = RuntimeError::on_line(chunk.lines[offset], message);
+        match chunk.spans[offset] {
+            Some(span) => error.at(span),
+            None => error,
+        }
+    }
+
+    fn limit_error(&self, limit: Limit) -> RuntimeError {
+        RuntimeError {
+            kind: RuntimeErrorKind::LimitExceeded(limit),
+            ..self.error(&limit.to_string())
+        }
+    }
+
+    fn undefined_variable(&self, name: &str) -> RuntimeError {
+        self.error(&format!("undefined variable '{}'", name))
+    }
+
+    fn arity_error(&self, arity: usize, argc: usize) -> RuntimeError {
+        self.error(&format!("expected {} arguments but got {}", arity, argc))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::super::compiler;
+    use super::super::interpreter::OutputBuffer;
+    use super::super::parser;
+    use super::super::scanner;
+    use super::*;
+
+    fn compile(source: &str) -> Rc<FunctionProto> {
+        let tokens = scanner::Scanner::new(source).parse();
+        let (statements, errors) = parser::Parser::new(tokens).parse();
+        assert!(errors.is_empty(), "{:?}", errors);
+        compiler::Compiler::new().compile(&statements).unwrap()
+    }
+
+    // Every test runs with the collector stressed so that a missing root
+    // surfaces as a use of a collected object.
+    fn run(source: &str) -> Result<String, RuntimeError> {
+        let output = OutputBuffer::new();
+        let mut vm = Vm::with_output(Box::new(output.clone()));
+        vm.set_gc_stress(true);
+        vm.interpret(compile(source))?;
+        Ok(output.contents())
+    }
+
+    #[test]
+    fn objects_survive_stressed_collection() {
+        assert_eq!(
+            run(r#"
+                class Node {
+                    init(value, next) { this.value = value; this.next = next; }
+                    sum() {
+                        if (this.next == nil) return this.value;
+                        return this.value + this.next.sum();
+                    }
+                }
+                class Named < Node {
+                    label() { return "n" + "ode"; }
+                    sum() { return super.sum(); }
+                }
+                var list = nil;
+                for (var i = 1; i <= 10; i = i + 1) list = Named(i, list);
+                var method = list.label;
+                print list.sum();
+                print method() + "!";
+            "#),
+            Ok("55\nnode!\n".to_owned())
+        );
+    }
+
+    #[test]
+    fn garbage_is_collected() {
+        let mut vm = Vm::with_output(Box::new(OutputBuffer::new()));
+        vm.interpret(compile(
+            r#"
+                var kept = "a" + "b";
+                for (var i = 0; i < 5000; i = i + 1) {
+                    var s = "x" + "y";
+                }
+            "#,
+        ))
+        .unwrap();
+
+        assert!(vm.heap.len() < 2048);
+        assert_eq!(vm.globals.len(), 2);
+    }
+
+    #[test]
+    fn upvalues_are_closed_when_leaving_scope() {
+        assert_eq!(
+            run(r#"
+                var getters;
+                var setters;
+                {
+                    var a = 1;
+                    fun get() { return a; }
+                    fun set(v) { a = v; }
+                    getters = get;
+                    setters = set;
+                    a = 2;
+                }
+                print getters();
+                setters(3);
+                print getters();
+            "#),
+            Ok("2\n3\n".to_owned())
+        );
+    }
+
+    #[test]
+    fn loop_variables_are_captured_per_closure() {
+        assert_eq!(
+            run(r#"
+                var first;
+                var second;
+                for (var i = 1; i <= 2; i = i + 1) {
+                    var j = i;
+                    fun f() { return j; }
+                    if (i == 1) first = f; else second = f;
+                }
+                print first();
+                print second();
+            "#),
+            Ok("1\n2\n".to_owned())
+        );
+    }
+
+    #[test]
+    fn stack_overflow() {
+        let err = run("fun f() {\n  f();\n}\nf();").unwrap_err();
+        assert_eq!(err.to_string(), "line 2: stack overflow");
+        assert_eq!(err.span.map(|s| s.start..s.end), Some(14..15));
+    }
+
+    #[test]
+    fn globals_persist_between_runs() {
+        let output = OutputBuffer::new();
+        let mut vm = Vm::with_output(Box::new(output.clone()));
+        vm.set_gc_stress(true);
+
+        for source in ["var a = 1;", "print a + 1;", "print missing;", "print a;"] {
+            let _ = vm.interpret(compile(source));
+        }
+        assert_eq!(output.contents(), "2\n1\n");
+    }
+}